@@ -0,0 +1,735 @@
+//! Plain-text grid format for hand-written levels.
+//!
+//! A level is made of header lines followed by two grids, one per world. Leading and trailing
+//! whitespace is ignored on every line, as are empty lines and lines starting with `;`.
+//!
+//! ```text
+//! name: Tutorial
+//! author: Someone
+//! palette: simple #000000 #ffffff
+//! spawn: 1 3
+//! door: 6 3
+//! dark:
+//! ########
+//! #......#
+//! #......#
+//! #...^..#
+//! ########
+//! light:
+//! ........
+//! ........
+//! ........
+//! ........
+//! ........
+//! ```
+//!
+//! Headers:
+//! - `name` and `author`: free text, optional.
+//! - `palette`: `simple <dark> <light>`, `lerp <dark1> <dark2> <light1> <light2>` or `trippy`,
+//!   with colors written as `#rrggbb`. Optional, defaults to black and white.
+//! - `tile_size`: width and height of a tile in pixels. Optional, defaults to 16 by 16.
+//! - `spawn` and `door`: position in tiles, required.
+//! - `gold`, `silver` and `bronze`: medal target times in seconds, optional.
+//! - `spring`: `<dark|light> <x> <y> <force>`, the force of the spring at that tile in pixels
+//!   per tick. Springs without one use `Tile::DEFAULT_SPRING_FORCE`. Can be repeated.
+//! - `conveyor`: `<dark|light> <x> <y> <speed>`, the speed of the conveyor at that tile in tenths
+//!   of a pixel per tick. Conveyors without one use `Tile::DEFAULT_CONVEYOR_SPEED`. Can be
+//!   repeated.
+//! - `group`: `<dark|light> <x> <y> <group>`, the group of the switch or switch block at that
+//!   tile, from 0 to `Tile::MAX_SWITCH_GROUP`. Tiles without one are in group 0. Can be repeated.
+//! - `pair`: `<dark|light> <x> <y> <pair>`, the pair id of the teleporter at that tile, from 0 to
+//!   `Tile::MAX_TELEPORTER_PAIR`. Teleporters without one have pair id 0. Can be repeated.
+//! - `platform` and `saw`: `<dark|light> <speed> <x> <y> [<x> <y>...]`, a moving entity with
+//!   its speed in pixels per tick and its waypoints in tiles. Can be repeated.
+//!
+//! Grid glyphs:
+//!
+//! | Glyph             | Tile                                      |
+//! |-------------------|-------------------------------------------|
+//! | `.`               | `Tile::None`                              |
+//! | `#`               | `Tile::Solid`                             |
+//! | `^` `v` `<` `>`   | `Tile::Spike` facing up, down, left, right  |
+//! | `n` `u` `{` `}`   | `Tile::Spring` facing up, down, left, right |
+//! | `-` `\|`          | `Tile::Portal` on the horizontal, vertical axis |
+//! | `k`               | `Tile::Key`                               |
+//! | `c`               | `Tile::Checkpoint`                        |
+//! | `r` `g` `b` `y`   | `Tile::ColorKey` red, green, blue, yellow |
+//! | `R` `G` `B` `Y`   | `Tile::Lock` red, green, blue, yellow     |
+//! | `=` `_` `[` `]`   | `Tile::OneWay` facing up, down, left, right |
+//! | `%`               | `Tile::Crumbling`                         |
+//! | `(` `)`           | `Tile::Conveyor` moving left, right       |
+//! | `s` `S`           | `Tile::Switch` for its world, both worlds |
+//! | `w` `W`           | `Tile::SwitchBlock` solid, passable at first |
+//! | `t`               | `Tile::Teleporter`                        |
+//! | `~` `V` `A`       | `Tile::Gravity` flipping, pulling down, pulling up |
+//! | `i`               | `Tile::Ice`                               |
+//!
+//! Both grids must have the same size and every row of a grid must have the same width.
+
+use std::{fmt::Write, ops::RangeInclusive};
+
+use tetra::{graphics::Color, math::Vec2};
+
+use crate::{
+    entity::{Entity, EntityKind},
+    level::{Level, LevelError},
+    medal::MedalTimes,
+    palette::Palette,
+    tilemap::{Axis, Facing, GravityZone, KeyColor, Tile, Tilemap},
+    world::{World, WorldMode},
+};
+
+fn tile_to_char(tile: Tile) -> char {
+    match tile {
+        Tile::None => '.',
+        Tile::Solid => '#',
+        Tile::Spike(Facing::Up) => '^',
+        Tile::Spike(Facing::Down) => 'v',
+        Tile::Spike(Facing::Left) => '<',
+        Tile::Spike(Facing::Right) => '>',
+        Tile::Spring(Facing::Up, _) => 'n',
+        Tile::Spring(Facing::Down, _) => 'u',
+        Tile::Spring(Facing::Left, _) => '{',
+        Tile::Spring(Facing::Right, _) => '}',
+        Tile::Portal(Axis::Horizontal) => '-',
+        Tile::Portal(Axis::Vertical) => '|',
+        Tile::Key => 'k',
+        Tile::Checkpoint => 'c',
+        Tile::ColorKey(KeyColor::Red) => 'r',
+        Tile::ColorKey(KeyColor::Green) => 'g',
+        Tile::ColorKey(KeyColor::Blue) => 'b',
+        Tile::ColorKey(KeyColor::Yellow) => 'y',
+        Tile::Lock(KeyColor::Red) => 'R',
+        Tile::Lock(KeyColor::Green) => 'G',
+        Tile::Lock(KeyColor::Blue) => 'B',
+        Tile::Lock(KeyColor::Yellow) => 'Y',
+        Tile::OneWay(Facing::Up) => '=',
+        Tile::OneWay(Facing::Down) => '_',
+        Tile::OneWay(Facing::Left) => '[',
+        Tile::OneWay(Facing::Right) => ']',
+        Tile::Crumbling => '%',
+        Tile::Conveyor(Facing::Left, _) => '(',
+        // Conveyors only move sideways, other facings are never placed
+        Tile::Conveyor(_, _) => ')',
+        Tile::Switch(_, false) => 's',
+        Tile::Switch(_, true) => 'S',
+        Tile::SwitchBlock(_, true) => 'w',
+        Tile::SwitchBlock(_, false) => 'W',
+        Tile::Teleporter(_) => 't',
+        Tile::Gravity(GravityZone::Flip) => '~',
+        Tile::Gravity(GravityZone::Down) => 'V',
+        Tile::Gravity(GravityZone::Up) => 'A',
+        Tile::Ice => 'i',
+    }
+}
+
+fn char_to_tile(c: char) -> Option<Tile> {
+    let tile = match c {
+        '.' => Tile::None,
+        '#' => Tile::Solid,
+        '^' => Tile::Spike(Facing::Up),
+        'v' => Tile::Spike(Facing::Down),
+        '<' => Tile::Spike(Facing::Left),
+        '>' => Tile::Spike(Facing::Right),
+        'n' => Tile::Spring(Facing::Up, Tile::DEFAULT_SPRING_FORCE),
+        'u' => Tile::Spring(Facing::Down, Tile::DEFAULT_SPRING_FORCE),
+        '{' => Tile::Spring(Facing::Left, Tile::DEFAULT_SPRING_FORCE),
+        '}' => Tile::Spring(Facing::Right, Tile::DEFAULT_SPRING_FORCE),
+        '-' => Tile::Portal(Axis::Horizontal),
+        '|' => Tile::Portal(Axis::Vertical),
+        'k' => Tile::Key,
+        'c' => Tile::Checkpoint,
+        'r' => Tile::ColorKey(KeyColor::Red),
+        'g' => Tile::ColorKey(KeyColor::Green),
+        'b' => Tile::ColorKey(KeyColor::Blue),
+        'y' => Tile::ColorKey(KeyColor::Yellow),
+        'R' => Tile::Lock(KeyColor::Red),
+        'G' => Tile::Lock(KeyColor::Green),
+        'B' => Tile::Lock(KeyColor::Blue),
+        'Y' => Tile::Lock(KeyColor::Yellow),
+        '=' => Tile::OneWay(Facing::Up),
+        '_' => Tile::OneWay(Facing::Down),
+        '[' => Tile::OneWay(Facing::Left),
+        ']' => Tile::OneWay(Facing::Right),
+        '%' => Tile::Crumbling,
+        '(' => Tile::Conveyor(Facing::Left, Tile::DEFAULT_CONVEYOR_SPEED),
+        ')' => Tile::Conveyor(Facing::Right, Tile::DEFAULT_CONVEYOR_SPEED),
+        's' => Tile::Switch(0, false),
+        'S' => Tile::Switch(0, true),
+        'w' => Tile::SwitchBlock(0, true),
+        'W' => Tile::SwitchBlock(0, false),
+        't' => Tile::Teleporter(0),
+        '~' => Tile::Gravity(GravityZone::Flip),
+        'V' => Tile::Gravity(GravityZone::Down),
+        'A' => Tile::Gravity(GravityZone::Up),
+        'i' => Tile::Ice,
+        _ => return None,
+    };
+    Some(tile)
+}
+
+fn error(line: usize, message: impl Into<String>) -> LevelError {
+    LevelError::Ascii {
+        line,
+        message: message.into(),
+    }
+}
+
+fn color_to_hex(color: Color) -> String {
+    let channel = |c: f32| (c.clamp(0., 1.) * 255.).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.r),
+        channel(color.g),
+        channel(color.b)
+    )
+}
+
+fn parse_color(line: usize, s: &str) -> Result<Color, LevelError> {
+    let hex = s
+        .strip_prefix('#')
+        .filter(|h| h.len() == 6)
+        .ok_or_else(|| error(line, format!("invalid color '{}'", s)))?;
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .map_err(|_| error(line, format!("invalid color '{}'", s)))
+    };
+    Ok(Color::rgb8(channel(0)?, channel(2)?, channel(4)?))
+}
+
+fn parse_palette(line: usize, value: &str) -> Result<Palette, LevelError> {
+    let mut words = value.split_whitespace();
+    let kind = words.next().unwrap_or_default();
+    let colors = words
+        .map(|w| parse_color(line, w))
+        .collect::<Result<Vec<_>, _>>()?;
+    match (kind, colors.as_slice()) {
+        ("simple", &[dark, light]) => Ok(Palette::Simple { dark, light }),
+        ("lerp", &[dark1, dark2, light1, light2]) => Ok(Palette::Lerp {
+            dark1,
+            dark2,
+            light1,
+            light2,
+        }),
+        ("trippy", &[]) => Ok(Palette::Trippy),
+        _ => Err(error(line, format!("invalid palette '{}'", value))),
+    }
+}
+
+fn write_palette(palette: &Palette) -> String {
+    match *palette {
+        Palette::Simple { dark, light } => {
+            format!("simple {} {}", color_to_hex(dark), color_to_hex(light))
+        }
+        Palette::Lerp {
+            dark1,
+            dark2,
+            light1,
+            light2,
+        } => format!(
+            "lerp {} {} {} {}",
+            color_to_hex(dark1),
+            color_to_hex(dark2),
+            color_to_hex(light1),
+            color_to_hex(light2)
+        ),
+        Palette::Trippy => "trippy".into(),
+    }
+}
+
+fn parse_pair(line: usize, value: &str) -> Result<Vec2<f32>, LevelError> {
+    let numbers = value
+        .split_whitespace()
+        .map(|w| w.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| error(line, format!("invalid number in '{}'", value)))?;
+    match numbers.as_slice() {
+        &[x, y] => Ok(Vec2::new(x, y)),
+        _ => Err(error(
+            line,
+            format!("expected two numbers, got '{}'", value),
+        )),
+    }
+}
+
+fn parse_tile_size(line: usize, value: &str) -> Result<Vec2<f32>, LevelError> {
+    match parse_pair(line, value)? {
+        size if size.x > 0. && size.y > 0. && size.x.is_finite() && size.y.is_finite() => Ok(size),
+        _ => Err(error(line, format!("invalid tile size '{}'", value))),
+    }
+}
+
+fn parse_time(line: usize, value: &str) -> Result<u64, LevelError> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs >= 0. => Ok(World::secs_to_ticks(secs)),
+        _ => Err(error(line, format!("invalid time '{}'", value))),
+    }
+}
+
+fn parse_world(line: usize, word: Option<&str>, value: &str) -> Result<WorldMode, LevelError> {
+    match word {
+        Some("dark") => Ok(WorldMode::Dark),
+        Some("light") => Ok(WorldMode::Light),
+        _ => Err(error(line, format!("expected a world in '{}'", value))),
+    }
+}
+
+fn write_world(world: WorldMode) -> &'static str {
+    match world {
+        WorldMode::Dark => "dark",
+        WorldMode::Light => "light",
+    }
+}
+
+/// Force of a spring, speed of a conveyor, group of a switch or pair of a teleporter at a tile,
+/// set once the grids are read.
+struct TileSetting {
+    line: usize,
+    header: &'static str,
+    world: WorldMode,
+    tile: (usize, usize),
+    value: u8,
+}
+
+fn parse_tile_setting(
+    line: usize,
+    header: &'static str,
+    range: RangeInclusive<u8>,
+    value: &str,
+) -> Result<TileSetting, LevelError> {
+    let mut words = value.split_whitespace();
+    let world = parse_world(line, words.next(), value)?;
+    let numbers = words
+        .map(|w| w.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| error(line, format!("invalid number in '{}'", value)))?;
+    match numbers[..] {
+        [x, y, n] if n <= u8::MAX as usize && range.contains(&(n as u8)) => Ok(TileSetting {
+            line,
+            header,
+            world,
+            tile: (x, y),
+            value: n as u8,
+        }),
+        [_, _, n] => Err(error(
+            line,
+            format!(
+                "{} value {} is out of range {}-{}",
+                header,
+                n,
+                range.start(),
+                range.end()
+            ),
+        )),
+        _ => Err(error(
+            line,
+            format!("expected x, y and a value in '{}'", value),
+        )),
+    }
+}
+
+/// Header setting the value of a tile, if it isn't the default one.
+fn tile_setting(tile: Tile) -> Option<(&'static str, u8)> {
+    match tile {
+        Tile::Spring(_, force) if force != Tile::DEFAULT_SPRING_FORCE => Some(("spring", force)),
+        Tile::Conveyor(_, speed) if speed != Tile::DEFAULT_CONVEYOR_SPEED => {
+            Some(("conveyor", speed))
+        }
+        Tile::Switch(group, _) | Tile::SwitchBlock(group, _) if group != 0 => {
+            Some(("group", group))
+        }
+        Tile::Teleporter(pair) if pair != 0 => Some(("pair", pair)),
+        _ => None,
+    }
+}
+
+fn parse_entity(line: usize, kind: EntityKind, value: &str) -> Result<Entity, LevelError> {
+    let mut words = value.split_whitespace();
+    let world = parse_world(line, words.next(), value)?;
+    let numbers = words
+        .map(|w| w.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| error(line, format!("invalid number in '{}'", value)))?;
+    let Some((&speed, waypoints)) = numbers.split_first() else {
+        return Err(error(line, format!("expected a speed in '{}'", value)));
+    };
+    if waypoints.is_empty() || waypoints.len() % 2 != 0 {
+        return Err(error(
+            line,
+            format!("expected waypoint pairs in '{}'", value),
+        ));
+    }
+    Ok(Entity {
+        kind,
+        world,
+        waypoints: waypoints
+            .chunks(2)
+            .map(|pair| Vec2::new(pair[0], pair[1]))
+            .collect(),
+        speed,
+    })
+}
+
+fn build_tilemap(
+    line: usize,
+    rows: &[Vec<Tile>],
+    tile_size: Vec2<f32>,
+) -> Result<Tilemap, LevelError> {
+    let width = rows.first().map(Vec::len).unwrap_or(0);
+    if width == 0 {
+        return Err(error(line, "empty grid"));
+    }
+    let mut tilemap = Tilemap::new((width, rows.len()), tile_size.into_tuple());
+    for (y, row) in rows.iter().enumerate() {
+        if row.len() != width {
+            return Err(error(
+                line + y + 1,
+                format!("row is {} tiles wide, expected {}", row.len(), width),
+            ));
+        }
+        for (x, tile) in row.iter().enumerate() {
+            tilemap.set_tile_usize((x, y), *tile);
+        }
+    }
+    Ok(tilemap)
+}
+
+enum Section {
+    Header,
+    Dark,
+    Light,
+}
+
+pub fn parse(input: &str) -> Result<Level, LevelError> {
+    let mut name = Level::DEFAULT_LEVEL_NAME.to_string();
+    let mut author = Level::DEFAULT_AUTHOR_NAME.to_string();
+    let mut palette = Palette::default();
+    let mut tile_size = Vec2::from(Level::DEFAULT_TILE_SIZE);
+    let mut spawn = None;
+    let mut door = None;
+    let mut medals = MedalTimes::default();
+    let mut entities = vec![];
+    let mut settings = vec![];
+    let mut dark = (0, vec![]);
+    let mut light = (0, vec![]);
+    let mut section = Section::Header;
+
+    for (index, raw_line) in input.lines().enumerate() {
+        let line = index + 1;
+        let text = raw_line.trim();
+        if text.is_empty() || text.starts_with(';') {
+            continue;
+        }
+        match text {
+            "dark:" => {
+                section = Section::Dark;
+                dark.0 = line;
+                continue;
+            }
+            "light:" => {
+                section = Section::Light;
+                light.0 = line;
+                continue;
+            }
+            _ => {}
+        }
+        match section {
+            Section::Header => {
+                let (key, value) = text
+                    .split_once(':')
+                    .ok_or_else(|| error(line, format!("expected a header, got '{}'", text)))?;
+                let value = value.trim();
+                match key.trim() {
+                    "name" => name = value.into(),
+                    "author" => author = value.into(),
+                    "palette" => palette = parse_palette(line, value)?,
+                    "tile_size" => tile_size = parse_tile_size(line, value)?,
+                    "spawn" => spawn = Some(parse_pair(line, value)?),
+                    "door" => door = Some(parse_pair(line, value)?),
+                    "gold" => medals.gold = Some(parse_time(line, value)?),
+                    "silver" => medals.silver = Some(parse_time(line, value)?),
+                    "bronze" => medals.bronze = Some(parse_time(line, value)?),
+                    "spring" => settings.push(parse_tile_setting(
+                        line,
+                        "spring",
+                        1..=Tile::MAX_SPRING_FORCE,
+                        value,
+                    )?),
+                    "conveyor" => settings.push(parse_tile_setting(
+                        line,
+                        "conveyor",
+                        1..=Tile::MAX_CONVEYOR_SPEED,
+                        value,
+                    )?),
+                    "group" => settings.push(parse_tile_setting(
+                        line,
+                        "group",
+                        0..=Tile::MAX_SWITCH_GROUP,
+                        value,
+                    )?),
+                    "pair" => settings.push(parse_tile_setting(
+                        line,
+                        "pair",
+                        0..=Tile::MAX_TELEPORTER_PAIR,
+                        value,
+                    )?),
+                    "platform" => entities.push(parse_entity(line, EntityKind::Platform, value)?),
+                    "saw" => entities.push(parse_entity(line, EntityKind::Saw, value)?),
+                    other => return Err(error(line, format!("unknown header '{}'", other))),
+                }
+            }
+            Section::Dark | Section::Light => {
+                let row = text
+                    .chars()
+                    .map(|c| {
+                        char_to_tile(c)
+                            .ok_or_else(|| error(line, format!("unknown tile glyph '{}'", c)))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                match section {
+                    Section::Dark => dark.1.push(row),
+                    _ => light.1.push(row),
+                }
+            }
+        }
+    }
+
+    let spawn = spawn.ok_or_else(|| error(0, "missing 'spawn' header"))?;
+    let door = door.ok_or_else(|| error(0, "missing 'door' header"))?;
+    if dark.0 == 0 {
+        return Err(error(0, "missing 'dark:' grid"));
+    }
+    if light.0 == 0 {
+        return Err(error(0, "missing 'light:' grid"));
+    }
+    let mut dark_tilemap = build_tilemap(dark.0, &dark.1, tile_size)?;
+    let mut light_tilemap = build_tilemap(light.0, &light.1, tile_size)?;
+    if dark_tilemap.size() != light_tilemap.size() {
+        return Err(error(light.0, "dark and light grids have different sizes"));
+    }
+
+    for setting in settings {
+        let tilemap = match setting.world {
+            WorldMode::Dark => &mut dark_tilemap,
+            WorldMode::Light => &mut light_tilemap,
+        };
+        let tile = match (setting.header, tilemap.get_tile_usize(setting.tile)) {
+            ("spring", Some(Tile::Spring(facing, _))) => Tile::Spring(facing, setting.value),
+            ("conveyor", Some(Tile::Conveyor(facing, _))) => Tile::Conveyor(facing, setting.value),
+            ("group", Some(Tile::Switch(_, both_worlds))) => {
+                Tile::Switch(setting.value, both_worlds)
+            }
+            ("group", Some(Tile::SwitchBlock(_, solid))) => Tile::SwitchBlock(setting.value, solid),
+            ("pair", Some(Tile::Teleporter(_))) => Tile::Teleporter(setting.value),
+            _ => {
+                return Err(error(
+                    setting.line,
+                    format!(
+                        "no tile taking a {} at {} {}",
+                        setting.header, setting.tile.0, setting.tile.1
+                    ),
+                ))
+            }
+        };
+        tilemap.set_tile_usize(setting.tile, tile);
+    }
+
+    // Waypoints are written in tiles, the tile size may come after them
+    for entity in &mut entities {
+        for waypoint in &mut entity.waypoints {
+            *waypoint *= tile_size;
+        }
+    }
+
+    Ok(Level {
+        name,
+        author,
+        dark_tilemap,
+        light_tilemap,
+        palette,
+        spawn_pos: spawn * tile_size,
+        end_pos: door * tile_size,
+        medals,
+        entities,
+    })
+}
+
+fn write_grid(out: &mut String, tilemap: &Tilemap) {
+    let width = tilemap.size().x;
+    tilemap.run_for_each_tile(|(x, _), tile| {
+        out.push(tile_to_char(*tile));
+        if x + 1 == width {
+            out.push('\n');
+        }
+    });
+}
+
+pub fn write(level: &Level) -> String {
+    let tile_size = level.dark_tilemap.tile_size();
+    let spawn = level.spawn_pos / tile_size;
+    let door = level.end_pos / tile_size;
+    let mut out = String::new();
+    // Writing to a String cannot fail
+    let _ = writeln!(out, "name: {}", level.name);
+    let _ = writeln!(out, "author: {}", level.author);
+    let _ = writeln!(out, "palette: {}", write_palette(&level.palette));
+    let _ = writeln!(out, "tile_size: {} {}", tile_size.x, tile_size.y);
+    let _ = writeln!(out, "spawn: {} {}", spawn.x, spawn.y);
+    let _ = writeln!(out, "door: {} {}", door.x, door.y);
+    for (key, time) in [
+        ("gold", level.medals.gold),
+        ("silver", level.medals.silver),
+        ("bronze", level.medals.bronze),
+    ] {
+        if let Some(ticks) = time {
+            let _ = writeln!(out, "{}: {}", key, World::ticks_to_secs(ticks));
+        }
+    }
+    for (world, tilemap) in [
+        (WorldMode::Dark, &level.dark_tilemap),
+        (WorldMode::Light, &level.light_tilemap),
+    ] {
+        tilemap.run_for_each_tile(|(x, y), tile| {
+            if let Some((header, value)) = tile_setting(*tile) {
+                let _ = writeln!(
+                    out,
+                    "{}: {} {} {} {}",
+                    header,
+                    write_world(world),
+                    x,
+                    y,
+                    value
+                );
+            }
+        });
+    }
+    for entity in &level.entities {
+        let kind = match entity.kind {
+            EntityKind::Platform => "platform",
+            EntityKind::Saw => "saw",
+        };
+        let _ = write!(
+            out,
+            "{}: {} {}",
+            kind,
+            write_world(entity.world),
+            entity.speed
+        );
+        for waypoint in &entity.waypoints {
+            let waypoint = *waypoint / tile_size;
+            let _ = write!(out, " {} {}", waypoint.x, waypoint.y);
+        }
+        out.push('\n');
+    }
+    out.push_str("dark:\n");
+    write_grid(&mut out, &level.dark_tilemap);
+    out.push_str("light:\n");
+    write_grid(&mut out, &level.light_tilemap);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use tetra::math::Vec2;
+
+    use super::{parse, write};
+    use crate::{
+        level::LevelError,
+        palette::Palette,
+        tilemap::{Axis, Facing, Tile},
+        world::WorldMode,
+    };
+
+    const LEVEL: &str = "
+        name: Test
+        author: Tester
+        palette: lerp #000000 #102030 #ffffff #a0b0c0
+        spawn: 1 2
+        door: 4 2
+        gold: 2.5
+        bronze: 10
+        spring: light 3 2 6
+        conveyor: dark 1 3 35
+        platform: dark 1.5 1 1 4 1
+        saw: light 2 2 2
+        dark:
+        ######
+        #.k..#
+        #..^.#
+        #()###
+        ######
+        light:
+        ......
+        .-|...
+        .n{}u.
+        ......
+        ......
+    ";
+
+    #[test]
+    fn parse_level() {
+        let level = parse(LEVEL).unwrap();
+        assert_eq!(level.name, "Test");
+        assert_eq!(level.author, "Tester");
+        assert!(matches!(level.palette, Palette::Lerp { .. }));
+        assert_eq!(level.spawn_pos, Vec2::new(16., 32.));
+        assert_eq!(level.end_pos, Vec2::new(64., 32.));
+        assert_eq!(level.medals.gold, Some(150));
+        assert_eq!(level.medals.silver, None);
+        assert_eq!(level.medals.bronze, Some(600));
+        assert_eq!(level.entities.len(), 2);
+        assert_eq!(
+            level.entities[0].waypoints,
+            vec![Vec2::new(16., 16.), Vec2::new(64., 16.)]
+        );
+        assert_eq!(level.entities[1].world, WorldMode::Light);
+        assert_eq!(level.dark_tilemap.size(), Vec2::new(6, 5));
+        assert_eq!(
+            level.dark_tilemap.get_tile_usize((1, 3)),
+            Some(Tile::Conveyor(Facing::Left, 35))
+        );
+        assert_eq!(
+            level.dark_tilemap.get_tile_usize((2, 3)),
+            Some(Tile::Conveyor(Facing::Right, Tile::DEFAULT_CONVEYOR_SPEED))
+        );
+        assert_eq!(level.dark_tilemap.get_tile_usize((2, 1)), Some(Tile::Key));
+        assert_eq!(
+            level.dark_tilemap.get_tile_usize((3, 2)),
+            Some(Tile::Spike(Facing::Up))
+        );
+        assert_eq!(
+            level.light_tilemap.get_tile_usize((1, 1)),
+            Some(Tile::Portal(Axis::Horizontal))
+        );
+        assert_eq!(
+            level.light_tilemap.get_tile_usize((3, 2)),
+            Some(Tile::Spring(Facing::Right, 6))
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let level = parse(LEVEL).unwrap();
+        let text = write(&level);
+        let reparsed = parse(&text).unwrap();
+        assert_eq!(write(&reparsed), text);
+        assert_eq!(reparsed.to_bytes().unwrap(), level.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn mismatched_grids() {
+        let res = parse("spawn: 0 0\ndoor: 0 0\ndark:\n..\n..\nlight:\n...\n...");
+        assert!(matches!(res, Err(LevelError::Ascii { line: 6, .. })));
+    }
+
+    #[test]
+    fn tile_size_must_be_positive() {
+        for size in ["0 0", "16 -16", "inf 16"] {
+            let res = parse(&format!(
+                "tile_size: {}\nspawn: 0 0\ndoor: 0 0\ndark:\n.\nlight:\n.",
+                size
+            ));
+            assert!(matches!(res, Err(LevelError::Ascii { line: 1, .. })));
+        }
+    }
+}
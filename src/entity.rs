@@ -0,0 +1,144 @@
+use std::f32::consts::PI;
+
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use tetra::{
+    graphics::{Color, DrawParams, Rectangle},
+    math::Vec2,
+};
+
+use crate::{world::WorldMode, Assets};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display)]
+pub enum EntityKind {
+    /// Solid block carrying the player standing on it.
+    #[default]
+    Platform,
+    /// Hazard killing the player on touch.
+    Saw,
+}
+
+/// Platform or hazard going back and forth along a path of waypoints.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub world: WorldMode,
+    /// Positions of the entity's top left corner, in pixels.
+    pub waypoints: Vec<Vec2<f32>>,
+    /// Pixels travelled every tick.
+    pub speed: f32,
+}
+
+impl Entity {
+    pub const DEFAULT_SPEED: f32 = 1.;
+    const SAW_GAP: f32 = 2. / 16.;
+
+    pub fn new(kind: EntityKind, world: WorldMode) -> Entity {
+        Entity {
+            kind,
+            world,
+            waypoints: Vec::new(),
+            speed: Self::DEFAULT_SPEED,
+        }
+    }
+
+    fn path_length(&self) -> f32 {
+        self.waypoints
+            .windows(2)
+            .map(|pair| pair[0].distance(pair[1]))
+            .sum()
+    }
+
+    /// Position after `ticks`, going from the first waypoint to the last and back.
+    pub fn position(&self, ticks: u64) -> Vec2<f32> {
+        let Some(&first) = self.waypoints.first() else {
+            return Vec2::zero();
+        };
+        let length = self.path_length();
+        if length == 0. || self.speed <= 0. {
+            return first;
+        }
+        let round_trip = 2. * length as f64;
+        let mut distance = (ticks as f64 * self.speed as f64 % round_trip) as f32;
+        if distance > length {
+            distance = 2. * length - distance;
+        }
+        for pair in self.waypoints.windows(2) {
+            let segment = pair[0].distance(pair[1]);
+            if distance <= segment {
+                return Vec2::lerp(pair[0], pair[1], distance / segment);
+            }
+            distance -= segment;
+        }
+        self.waypoints[self.waypoints.len() - 1]
+    }
+
+    /// Area the entity collides with when at `pos`.
+    pub fn rect(&self, pos: Vec2<f32>, tile_size: Vec2<f32>) -> Rectangle {
+        match self.kind {
+            EntityKind::Platform => Rectangle::new(pos.x, pos.y, tile_size.x, tile_size.y),
+            EntityKind::Saw => {
+                let gap = tile_size * Self::SAW_GAP;
+                Rectangle::new(
+                    pos.x + gap.x,
+                    pos.y + gap.y,
+                    tile_size.x - gap.x * 2.,
+                    tile_size.y - gap.y * 2.,
+                )
+            }
+        }
+    }
+
+    /// Draws the entity at `pos`, with saws turned by `spin` radians.
+    pub fn draw(
+        &self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        pos: Vec2<f32>,
+        tile_size: Vec2<f32>,
+        color: Color,
+        spin: f32,
+    ) {
+        match self.kind {
+            EntityKind::Platform => {
+                assets
+                    .tile
+                    .draw(ctx, DrawParams::new().position(pos).color(color));
+            }
+            EntityKind::Saw => {
+                let offset = tile_size / 2.;
+                for i in 0..4 {
+                    assets.spike.draw(
+                        ctx,
+                        DrawParams::new()
+                            .position(pos + offset)
+                            .rotation(spin + i as f32 * PI / 2.)
+                            .origin(offset)
+                            .color(color),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tetra::math::Vec2;
+
+    use super::{Entity, EntityKind};
+    use crate::world::WorldMode;
+
+    #[test]
+    fn goes_back_and_forth() {
+        let mut entity = Entity::new(EntityKind::Platform, WorldMode::Dark);
+        entity.waypoints = vec![Vec2::new(0., 0.), Vec2::new(10., 0.), Vec2::new(10., 10.)];
+        entity.speed = 2.;
+        assert_eq!(entity.position(0), Vec2::new(0., 0.));
+        assert_eq!(entity.position(4), Vec2::new(8., 0.));
+        assert_eq!(entity.position(7), Vec2::new(10., 4.));
+        assert_eq!(entity.position(10), Vec2::new(10., 10.));
+        assert_eq!(entity.position(13), Vec2::new(10., 4.));
+        assert_eq!(entity.position(20), Vec2::new(0., 0.));
+    }
+}
@@ -0,0 +1,43 @@
+use tetra::graphics::{self, BlendState, Color};
+
+use crate::{input::InputFrame, level::Level, world::World, Assets};
+
+/// Plays back the inputs of a previous run alongside the player.
+pub struct Ghost {
+    inputs: Vec<InputFrame>,
+    world: World,
+    cursor: usize,
+}
+
+impl Ghost {
+    const ALPHA: f32 = 0.35;
+
+    pub fn new(level: &Level, inputs: Vec<InputFrame>) -> Ghost {
+        Ghost {
+            inputs,
+            world: World::new(level.clone()),
+            cursor: 0,
+        }
+    }
+
+    pub fn step(&mut self) {
+        if let Some(input) = self.inputs.get(self.cursor) {
+            self.world.step(input, World::TICK);
+            self.cursor += 1;
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.world.win() || self.cursor >= self.inputs.len()
+    }
+
+    pub fn draw(&self, ctx: &mut tetra::Context, assets: &Assets, alpha: f32) {
+        if self.finished() {
+            return;
+        }
+        graphics::set_blend_state(ctx, BlendState::add(false));
+        self.world
+            .draw_player(ctx, assets, alpha, Color::WHITE.with_alpha(Self::ALPHA));
+        graphics::reset_blend_state(ctx);
+    }
+}
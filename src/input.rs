@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use tetra::input::{self, Key};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputFrame {
+    pub left: bool,
+    pub right: bool,
+    pub jump: bool,
+    pub jump_held: bool,
+    pub reset: bool,
+}
+
+impl InputFrame {
+    pub fn from_context(ctx: &tetra::Context) -> InputFrame {
+        InputFrame {
+            left: input::is_key_down(ctx, Key::Left),
+            right: input::is_key_down(ctx, Key::Right),
+            jump: input::is_key_pressed(ctx, Key::Space),
+            jump_held: input::is_key_down(ctx, Key::Space),
+            reset: input::is_key_pressed(ctx, Key::R),
+        }
+    }
+
+    /// Combines the input of a new frame with input that no tick has consumed yet, so key
+    /// presses aren't lost on frames where no tick runs.
+    pub fn merge(&mut self, next: &InputFrame) {
+        self.left = next.left;
+        self.right = next.right;
+        self.jump_held = next.jump_held;
+        self.jump |= next.jump;
+        self.reset |= next.reset;
+    }
+
+    /// Forgets key presses once a tick has consumed them, keeping held keys.
+    pub fn consume_presses(&mut self) {
+        self.jump = false;
+        self.reset = false;
+    }
+}
@@ -4,12 +4,19 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use bincode::Options;
 use serde::{Deserialize, Serialize};
 use tetra::math::Vec2;
 use zip::{write::FileOptions, ZipArchive, ZipWriter};
 
-use crate::{palette::Palette, tilemap::Tilemap};
+use crate::{
+    ascii,
+    entity::Entity,
+    medal::MedalTimes,
+    migration,
+    palette::Palette,
+    tilemap::Tilemap,
+    validate::{self, Diagnostic},
+};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Level {
@@ -20,6 +27,44 @@ pub struct Level {
     pub palette: Palette,
     pub spawn_pos: Vec2<f32>,
     pub end_pos: Vec2<f32>,
+    #[serde(default)]
+    pub medals: MedalTimes,
+    #[serde(default)]
+    pub entities: Vec<Entity>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelFormat {
+    Binary,
+    Ron,
+    Ascii,
+}
+
+impl LevelFormat {
+    pub const BINARY_EXTENSION: &'static str = "umdx";
+    pub const RON_EXTENSION: &'static str = "ron";
+    pub const ASCII_EXTENSION: &'static str = "umda";
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<LevelFormat> {
+        let ext = path.as_ref().extension()?;
+        if ext == Self::BINARY_EXTENSION {
+            Some(LevelFormat::Binary)
+        } else if ext == Self::RON_EXTENSION {
+            Some(LevelFormat::Ron)
+        } else if ext == Self::ASCII_EXTENSION {
+            Some(LevelFormat::Ascii)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LevelFormat::Binary => Self::BINARY_EXTENSION,
+            LevelFormat::Ron => Self::RON_EXTENSION,
+            LevelFormat::Ascii => Self::ASCII_EXTENSION,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -28,70 +73,259 @@ pub enum LevelError {
     Serialization(Box<bincode::ErrorKind>),
     Deserialization(Box<bincode::ErrorKind>),
     Zip(zip::result::ZipError),
+    InvalidHeader,
+    RonSerialization(ron::Error),
+    RonDeserialization(ron::error::SpannedError),
+    Ascii { line: usize, message: String },
+    MissingLevel(String),
+    NewerVersion { found: u16, supported: u16 },
+    UnknownFlags(u16),
+}
+
+impl Default for Level {
+    fn default() -> Level {
+        Level {
+            name: Self::DEFAULT_LEVEL_NAME.to_string(),
+            author: Self::DEFAULT_AUTHOR_NAME.to_string(),
+            dark_tilemap: Tilemap::new(Self::DEFAULT_TILEMAP_SIZE, Self::DEFAULT_TILE_SIZE),
+            light_tilemap: Tilemap::new(Self::DEFAULT_TILEMAP_SIZE, Self::DEFAULT_TILE_SIZE),
+            palette: Palette::default(),
+            spawn_pos: Vec2::zero(),
+            end_pos: Vec2::zero(),
+            medals: MedalTimes::default(),
+            entities: Vec::new(),
+        }
+    }
 }
 
 impl Level {
+    pub const DEFAULT_LEVEL_NAME: &'static str = "Untitled Level";
+    pub const DEFAULT_AUTHOR_NAME: &'static str = "Unnamed Mapmaker";
+    pub const DEFAULT_TILEMAP_SIZE: (usize, usize) = (80, 45);
+    pub const DEFAULT_TILE_SIZE: (f32, f32) = (16., 16.);
+
     pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Level, LevelError> {
+        let format = LevelFormat::from_path(&path).unwrap_or(LevelFormat::Binary);
         let bytes = fs::read(path).map_err(LevelError::Io)?;
-        Self::load_bytes(&bytes)
+        Self::load_bytes_as(&bytes, format)
     }
 
     pub fn load_bytes(bytes: &[u8]) -> Result<Level, LevelError> {
-        bincode::options()
-            .with_varint_encoding()
-            .with_big_endian()
-            .deserialize(bytes)
-            .map_err(LevelError::Deserialization)
+        migration::decode(bytes)
+    }
+
+    pub fn load_bytes_as(bytes: &[u8], format: LevelFormat) -> Result<Level, LevelError> {
+        match format {
+            LevelFormat::Binary => Self::load_bytes(bytes),
+            LevelFormat::Ron => migration::decode_ron(bytes),
+            LevelFormat::Ascii => {
+                let text = String::from_utf8_lossy(bytes);
+                Self::from_ascii(&text)
+            }
+        }
+    }
+
+    pub fn from_ascii(text: &str) -> Result<Level, LevelError> {
+        ascii::parse(text)
+    }
+
+    pub fn to_ascii(&self) -> String {
+        ascii::write(self)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, LevelError> {
+        migration::encode(self)
+    }
+
+    pub fn to_ron_string(&self) -> Result<String, LevelError> {
+        migration::encode_ron(self)
+    }
+
+    /// Stable 64-bit FNV-1a hash of the level's contents, independent of the file format.
+    pub fn content_hash(&self) -> Result<u64, LevelError> {
+        let bytes = migration::encode_payload(self)?;
+        let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        });
+        Ok(hash)
+    }
+
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        validate::validate(self)
     }
 
     pub fn save_file<P: AsRef<Path>>(&self, path: P) -> Result<(), LevelError> {
-        let bytes = bincode::options()
-            .with_varint_encoding()
-            .with_big_endian()
-            .serialize(self)
-            .map_err(LevelError::Serialization)?;
+        let bytes = match LevelFormat::from_path(&path).unwrap_or(LevelFormat::Binary) {
+            LevelFormat::Binary => self.to_bytes()?,
+            LevelFormat::Ron => self.to_ron_string()?.into_bytes(),
+            LevelFormat::Ascii => self.to_ascii().into_bytes(),
+        };
         fs::write(path, bytes).map_err(LevelError::Io)
     }
 }
 
-#[derive(Default)]
+/// Which levels of a pack can be picked from the level select.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum UnlockRule {
+    /// Every level can be played right away.
+    Open,
+    /// A level unlocks once every level before it is completed.
+    #[default]
+    Sequential,
+    /// Like `Sequential`, but up to this many earlier levels can be left uncompleted.
+    Skips(usize),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PackManifest {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub author: String,
+    pub description: String,
+    pub version: String,
+    pub levels: Vec<String>,
+    pub unlock: UnlockRule,
+}
+
+impl PackManifest {
+    pub const FILE_NAME: &'static str = "pack.ron";
+
+    pub fn load_bytes(bytes: &[u8]) -> Result<PackManifest, LevelError> {
+        ron::de::from_bytes(bytes).map_err(LevelError::RonDeserialization)
+    }
+
+    pub fn is_manifest<P: AsRef<Path>>(path: P) -> bool {
+        path.as_ref()
+            .file_name()
+            .is_some_and(|f| f == Self::FILE_NAME)
+    }
+
+    /// Orders level files following the manifest's level list, or alphabetically if the
+    /// manifest doesn't list any level. Files missing from a non-empty list are skipped.
+    pub fn order<T>(&self, mut files: Vec<(String, T)>) -> Result<Vec<T>, LevelError> {
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        if self.levels.is_empty() {
+            return Ok(files.into_iter().map(|(_, f)| f).collect());
+        }
+        let mut ordered = Vec::with_capacity(self.levels.len());
+        for level in &self.levels {
+            let index = files
+                .iter()
+                .position(|(name, _)| name == level)
+                .ok_or_else(|| LevelError::MissingLevel(level.clone()))?;
+            ordered.push(files.remove(index).1);
+        }
+        for (name, _) in &files {
+            println!(
+                "Level {} is not listed in the pack manifest, skipping",
+                name
+            );
+        }
+        Ok(ordered)
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct LevelPack {
+    pub id: String,
     pub name: String,
+    pub author: String,
+    pub description: String,
+    pub version: String,
     pub levels: Vec<Level>,
+    pub level_files: Vec<String>,
+    pub unlock: UnlockRule,
     pub location: PathBuf,
     pub is_zip: bool,
+    pub diagnostics: Vec<(String, Diagnostic)>,
 }
 
 impl LevelPack {
+    fn with_manifest(
+        manifest: PackManifest,
+        fallback_name: Option<String>,
+        levels: Vec<(String, Level)>,
+        location: PathBuf,
+        is_zip: bool,
+    ) -> LevelPack {
+        // Levels with problems stay in the pack, which shows them in the level select
+        let mut diagnostics = vec![];
+        for (file, level) in &levels {
+            for diagnostic in level.validate() {
+                println!("{}: {}", file, diagnostic);
+                diagnostics.push((file.clone(), diagnostic));
+            }
+        }
+        let (level_files, levels) = levels.into_iter().unzip();
+        let PackManifest {
+            id,
+            name,
+            author,
+            description,
+            version,
+            unlock,
+            ..
+        } = manifest;
+        let name = name.or(fallback_name).unwrap_or("Unnamed Pack".into());
+        LevelPack {
+            // Packs without an explicit id are told apart by name, which stays the same when
+            // a directory is zipped up
+            id: id.unwrap_or(name.clone()),
+            name,
+            author,
+            description,
+            version,
+            levels,
+            level_files,
+            unlock,
+            location,
+            is_zip,
+            diagnostics,
+        }
+    }
+
+    /// Problems found in one of the pack's level files.
+    pub fn level_diagnostics<'a>(&'a self, file: &'a str) -> impl Iterator<Item = &'a Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(move |(f, _)| f == file)
+            .map(|(_, diagnostic)| diagnostic)
+    }
+
     pub fn from_directory<P: AsRef<Path>>(path: P) -> Result<LevelPack, LevelError> {
-        let mut levels = vec![];
-        let entries: std::io::Result<Vec<fs::DirEntry>> = fs::read_dir(path.as_ref())
-            .map_err(LevelError::Io)?
-            .collect();
-        let mut entries = entries.map_err(LevelError::Io)?;
-        entries.sort_by_key(|e| e.path());
-        for entry in &entries {
+        let mut files = vec![];
+        let mut manifest = PackManifest::default();
+        for entry in fs::read_dir(path.as_ref()).map_err(LevelError::Io)? {
+            let entry = entry.map_err(LevelError::Io)?;
             let ft = entry.file_type().map_err(LevelError::Io)?;
             if ft.is_file() {
                 let path = entry.path();
-                if let Some(ext) = path.extension() {
-                    if ext == "umdx" {
-                        let level = Level::load_file(&path)?;
-                        levels.push(level);
-                    }
+                if PackManifest::is_manifest(&path) {
+                    let bytes = fs::read(&path).map_err(LevelError::Io)?;
+                    manifest = PackManifest::load_bytes(&bytes)?;
+                } else if LevelFormat::from_path(&path).is_some() {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    files.push((name.clone(), (name, path)));
                 }
             }
         }
-        Ok(LevelPack {
+        let mut levels = vec![];
+        for (name, path) in manifest.order(files)? {
+            let level = Level::load_file(&path)?;
+            levels.push((name, level));
+        }
+        let fallback_name = path
+            .as_ref()
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string());
+        Ok(LevelPack::with_manifest(
+            manifest,
+            fallback_name,
             levels,
-            name: path
-                .as_ref()
-                .file_name()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or("Unnamed Pack".into()),
-            location: path.as_ref().to_path_buf(),
-            is_zip: false,
-        })
+            path.as_ref().to_path_buf(),
+            false,
+        ))
     }
 
     pub fn from_zip_file<P: AsRef<Path>>(path: P) -> Result<LevelPack, LevelError> {
@@ -99,6 +333,7 @@ impl LevelPack {
         let reader = BufReader::new(f);
         let mut zip = ZipArchive::new(reader).map_err(LevelError::Zip)?;
         let mut files = vec![];
+        let mut manifest = PackManifest::default();
         for i in 0..zip.len() {
             let mut file = zip.by_index(i).map_err(LevelError::Zip)?;
             let path = {
@@ -107,30 +342,37 @@ impl LevelPack {
                     None => continue,
                 }
             };
-            if path.extension().is_some_and(|e| e == "umdx") {
+            if PackManifest::is_manifest(&path) {
+                let mut buf = vec![];
+                let _ = file.read_to_end(&mut buf).map_err(LevelError::Io)?;
+                manifest = PackManifest::load_bytes(&buf)?;
+            } else if let Some(format) = LevelFormat::from_path(&path) {
                 let mut buf = vec![];
                 let _ = file.read_to_end(&mut buf).map_err(LevelError::Io)?;
-                let bytes = buf.clone();
-                files.push((path.to_path_buf(), bytes));
+                // Zips may hold the pack in a top-level folder, which the manifest doesn't name
+                let name = match path.file_name() {
+                    Some(name) => name.to_string_lossy().to_string(),
+                    None => continue,
+                };
+                files.push((name.clone(), (name, format, buf)));
             }
         }
-        files.sort_by(|a, b| a.0.cmp(&b.0));
         let mut levels = vec![];
-        for (_, bytes) in files {
-            let level = Level::load_bytes(&bytes)?;
-            levels.push(level);
+        for (name, format, bytes) in manifest.order(files)? {
+            let level = Level::load_bytes_as(&bytes, format)?;
+            levels.push((name, level));
         }
-        let name = path
+        let fallback_name = path
             .as_ref()
             .file_stem()
-            .map(|f| f.to_string_lossy().to_string())
-            .unwrap_or("Unnamed Pack".into());
-        Ok(LevelPack {
-            name,
+            .map(|f| f.to_string_lossy().to_string());
+        Ok(LevelPack::with_manifest(
+            manifest,
+            fallback_name,
             levels,
-            location: path.as_ref().to_path_buf(),
-            is_zip: true,
-        })
+            path.as_ref().to_path_buf(),
+            true,
+        ))
     }
 
     pub fn get_packs_in_directory<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<LevelPack>> {
@@ -170,14 +412,27 @@ impl LevelPack {
 
     pub fn make_zip_from_dir<P: AsRef<Path>>(dir: P) -> Result<LevelPack, LevelError> {
         assert!(!dir.as_ref().is_file());
-        let files: Vec<PathBuf> = fs::read_dir(&dir)
+        let mut files: Vec<PathBuf> = fs::read_dir(&dir)
             .map_err(LevelError::Io)?
             .filter_map(Result::ok)
             .map(|f| f.path())
             .collect();
-        dbg!(&files);
+        let manifest_path = dir.as_ref().join(PackManifest::FILE_NAME);
+        if manifest_path.is_file() {
+            let bytes = fs::read(&manifest_path).map_err(LevelError::Io)?;
+            let manifest = PackManifest::load_bytes(&bytes)?;
+            // Only ship the levels the manifest lists, in addition to the manifest itself
+            if !manifest.levels.is_empty() {
+                let levels = files
+                    .into_iter()
+                    .filter(|f| LevelFormat::from_path(f).is_some())
+                    .filter_map(|f| Some((f.file_name()?.to_string_lossy().to_string(), f)))
+                    .collect();
+                files = manifest.order(levels)?;
+                files.push(manifest_path);
+            }
+        }
         let zip_path = dir.as_ref().with_extension("zip");
-        dbg!(&zip_path);
         {
             let file = File::create(&zip_path).map_err(LevelError::Io)?;
             let mut writer = ZipWriter::new(file);
@@ -200,3 +455,74 @@ impl LevelPack {
         Ok(pack)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{fs::File, io::Write};
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::{Level, LevelPack, PackManifest};
+
+    fn ascii_level(name: &str) -> String {
+        format!(
+            "
+            name: {name}
+            spawn: 1 1
+            door: 3 1
+            dark:
+            #####
+            #...#
+            #####
+            light:
+            .....
+            .....
+            .....
+            "
+        )
+    }
+
+    #[test]
+    fn zip_with_a_folder_follows_the_manifest() {
+        let path = std::env::temp_dir().join("upfall-zip-with-a-folder.zip");
+        {
+            let mut writer = ZipWriter::new(File::create(&path).unwrap());
+            let files = [
+                ("pack/a.umda", ascii_level("A")),
+                ("pack/b.umda", ascii_level("B")),
+                (
+                    "pack/pack.ron",
+                    r#"(name: Some("Folder"), levels: ["b.umda", "a.umda"])"#.to_string(),
+                ),
+            ];
+            for (name, contents) in files {
+                writer.start_file(name, FileOptions::default()).unwrap();
+                writer.write_all(contents.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        let pack = LevelPack::from_zip_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(pack.name, "Folder");
+        assert_eq!(pack.level_files, ["b.umda", "a.umda"]);
+        let names: Vec<_> = pack.levels.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["B", "A"]);
+    }
+
+    #[test]
+    fn broken_levels_stay_in_the_pack() {
+        let broken = Level::from_ascii(&ascii_level("Broken").replace("#...#", "##..#")).unwrap();
+        let levels = vec![
+            (
+                "a.umda".to_string(),
+                Level::from_ascii(&ascii_level("A")).unwrap(),
+            ),
+            ("broken.umda".to_string(), broken),
+        ];
+        let pack =
+            LevelPack::with_manifest(PackManifest::default(), None, levels, "".into(), false);
+        assert_eq!(pack.levels.len(), 2);
+        assert_eq!(pack.level_diagnostics("a.umda").count(), 0);
+        assert!(pack.level_diagnostics("broken.umda").any(|d| d.is_error()));
+    }
+}
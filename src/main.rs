@@ -5,12 +5,23 @@ use tetra::{
     window, ContextBuilder,
 };
 
+mod ascii;
+mod entity;
+mod ghost;
+mod input;
 mod level;
+mod medal;
+mod migration;
 mod palette;
 mod player;
+mod replay;
+mod save;
 mod scenes;
+mod solver;
+mod splits;
 mod tilemap;
 mod util;
+mod validate;
 mod world;
 
 pub struct Assets {
@@ -0,0 +1,82 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Medal {
+    Bronze,
+    Silver,
+    Gold,
+}
+
+impl fmt::Display for Medal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Medal::Bronze => write!(f, "Bronze"),
+            Medal::Silver => write!(f, "Silver"),
+            Medal::Gold => write!(f, "Gold"),
+        }
+    }
+}
+
+/// Target times chosen by the level author, in world ticks. A medal is earned by finishing the
+/// level in its time or faster.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MedalTimes {
+    pub gold: Option<u64>,
+    pub silver: Option<u64>,
+    pub bronze: Option<u64>,
+}
+
+impl MedalTimes {
+    pub fn get(&self, medal: Medal) -> Option<u64> {
+        match medal {
+            Medal::Bronze => self.bronze,
+            Medal::Silver => self.silver,
+            Medal::Gold => self.gold,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == MedalTimes::default()
+    }
+
+    /// Best medal earned by a completion time.
+    pub fn medal(&self, ticks: u64) -> Option<Medal> {
+        [Medal::Gold, Medal::Silver, Medal::Bronze]
+            .into_iter()
+            .find(|medal| self.get(*medal).is_some_and(|time| ticks <= time))
+    }
+
+    /// Whether the times set go from the hardest to the easiest medal.
+    pub fn is_ordered(&self) -> bool {
+        let times = [self.gold, self.silver, self.bronze];
+        let set = times.iter().flatten().collect::<Vec<_>>();
+        set.windows(2).all(|pair| pair[0] <= pair[1])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Medal, MedalTimes};
+
+    #[test]
+    fn earned_medals() {
+        let times = MedalTimes {
+            gold: Some(100),
+            silver: None,
+            bronze: Some(300),
+        };
+        assert_eq!(times.medal(90), Some(Medal::Gold));
+        assert_eq!(times.medal(100), Some(Medal::Gold));
+        assert_eq!(times.medal(200), Some(Medal::Bronze));
+        assert_eq!(times.medal(301), None);
+        assert!(times.is_ordered());
+        assert!(!MedalTimes {
+            gold: Some(400),
+            ..times
+        }
+        .is_ordered());
+    }
+}
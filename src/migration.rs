@@ -0,0 +1,508 @@
+use bincode::Options;
+use ron::ser::PrettyConfig;
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Serialize,
+};
+use tetra::math::Vec2;
+
+use crate::{
+    entity::Entity,
+    level::{Level, LevelError},
+    medal::MedalTimes,
+    palette::Palette,
+    tilemap::{Axis, Facing, KeyColor, Tile, Tilemap},
+};
+
+pub const MAGIC: [u8; 4] = *b"UMDX";
+pub const FORMAT_VERSION: u16 = 4;
+const HEADER_LEN: usize = MAGIC.len() + 2 + 2;
+/// Header flag bits this version understands. None are defined yet, so files with any flag set
+/// come from a newer writer and are rejected rather than loaded as if the flags weren't there.
+const KNOWN_FLAGS: u16 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub flags: u16,
+}
+
+impl Header {
+    pub fn current() -> Header {
+        Header {
+            version: FORMAT_VERSION,
+            flags: 0,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&self.version.to_be_bytes());
+        bytes[6..8].copy_from_slice(&self.flags.to_be_bytes());
+        bytes
+    }
+
+    /// Splits a level file into its header and payload.
+    ///
+    /// Files written before the container existed have no magic bytes and are reported as
+    /// version 0.
+    pub fn parse(bytes: &[u8]) -> Result<(Header, &[u8]), LevelError> {
+        if !bytes.starts_with(&MAGIC) {
+            return Ok((
+                Header {
+                    version: 0,
+                    flags: 0,
+                },
+                bytes,
+            ));
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(LevelError::InvalidHeader);
+        }
+        let header = Header {
+            version: u16::from_be_bytes([bytes[4], bytes[5]]),
+            flags: u16::from_be_bytes([bytes[6], bytes[7]]),
+        };
+        if header.flags & !KNOWN_FLAGS != 0 {
+            return Err(LevelError::UnknownFlags(header.flags & !KNOWN_FLAGS));
+        }
+        Ok((header, &bytes[HEADER_LEN..]))
+    }
+}
+
+pub fn options() -> impl Options {
+    bincode::options().with_varint_encoding().with_big_endian()
+}
+
+/// Serializes a level without the container header.
+pub fn encode_payload(level: &Level) -> Result<Vec<u8>, LevelError> {
+    options()
+        .serialize(level)
+        .map_err(LevelError::Serialization)
+}
+
+pub fn encode(level: &Level) -> Result<Vec<u8>, LevelError> {
+    let payload = encode_payload(level)?;
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&Header::current().to_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Level, LevelError> {
+    let (header, payload) = Header::parse(bytes)?;
+    migrate(header.version, &BinaryPayload(payload))
+}
+
+/// RON level file: the level tagged with the format version of its layout.
+#[derive(Serialize, Deserialize)]
+struct RonDocument<T> {
+    version: u16,
+    level: T,
+}
+
+pub fn encode_ron(level: &Level) -> Result<String, LevelError> {
+    let document = RonDocument {
+        version: FORMAT_VERSION,
+        level,
+    };
+    ron::ser::to_string_pretty(&document, PrettyConfig::default())
+        .map_err(LevelError::RonSerialization)
+}
+
+/// Reads a RON level file.
+///
+/// Files written before RON documents were versioned hold the bare level. They are read with
+/// the current layout, or with the version 3 one if they predate springs having a force.
+pub fn decode_ron(bytes: &[u8]) -> Result<Level, LevelError> {
+    match ron::de::from_bytes::<RonDocument<IgnoredAny>>(bytes) {
+        Ok(document) => migrate(
+            document.version,
+            &RonPayload {
+                bytes,
+                versioned: true,
+            },
+        ),
+        Err(_) => {
+            let payload = RonPayload {
+                bytes,
+                versioned: false,
+            };
+            migrate(FORMAT_VERSION, &payload).or_else(|err| migrate(3, &payload).map_err(|_| err))
+        }
+    }
+}
+
+/// Serialized level of a known format version, read as one of the layouts below.
+trait Payload {
+    fn read<T: DeserializeOwned>(&self) -> Result<T, LevelError>;
+}
+
+/// Bincode payload following the container header.
+struct BinaryPayload<'a>(&'a [u8]);
+
+impl Payload for BinaryPayload<'_> {
+    fn read<T: DeserializeOwned>(&self) -> Result<T, LevelError> {
+        options()
+            .deserialize(self.0)
+            .map_err(LevelError::Deserialization)
+    }
+}
+
+/// RON document, either a `RonDocument` or a bare level.
+struct RonPayload<'a> {
+    bytes: &'a [u8],
+    versioned: bool,
+}
+
+impl Payload for RonPayload<'_> {
+    fn read<T: DeserializeOwned>(&self) -> Result<T, LevelError> {
+        let level = if self.versioned {
+            ron::de::from_bytes::<RonDocument<T>>(self.bytes).map(|document| document.level)
+        } else {
+            ron::de::from_bytes(self.bytes)
+        };
+        level.map_err(LevelError::RonDeserialization)
+    }
+}
+
+/// `Tile` up to version 3, before springs had their own force.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+enum TileV3 {
+    None,
+    Solid,
+    Spike(Facing),
+    Portal(Axis),
+    Key,
+    Spring(Facing),
+    Checkpoint,
+    ColorKey(KeyColor),
+    Lock(KeyColor),
+    OneWay(Facing),
+    Crumbling,
+}
+
+impl From<TileV3> for Tile {
+    fn from(tile: TileV3) -> Tile {
+        match tile {
+            TileV3::None => Tile::None,
+            TileV3::Solid => Tile::Solid,
+            TileV3::Spike(facing) => Tile::Spike(facing),
+            TileV3::Portal(axis) => Tile::Portal(axis),
+            TileV3::Key => Tile::Key,
+            TileV3::Spring(facing) => Tile::Spring(facing, Tile::DEFAULT_SPRING_FORCE),
+            TileV3::Checkpoint => Tile::Checkpoint,
+            TileV3::ColorKey(color) => Tile::ColorKey(color),
+            TileV3::Lock(color) => Tile::Lock(color),
+            TileV3::OneWay(facing) => Tile::OneWay(facing),
+            TileV3::Crumbling => Tile::Crumbling,
+        }
+    }
+}
+
+/// `Tilemap` up to version 3, holding `TileV3`s.
+#[derive(Serialize, Deserialize)]
+struct TilemapV3 {
+    tiles: Vec<TileV3>,
+    tilemap_size: Vec2<usize>,
+    tile_size: Vec2<f32>,
+}
+
+impl From<TilemapV3> for Tilemap {
+    fn from(tilemap: TilemapV3) -> Tilemap {
+        let size = tilemap.tilemap_size;
+        let mut converted = Tilemap::new(size.into_tuple(), tilemap.tile_size.into_tuple());
+        for (i, tile) in tilemap.tiles.into_iter().enumerate() {
+            converted.set_tile_usize((i % size.x, i / size.x), tile.into());
+        }
+        converted
+    }
+}
+
+/// `Level` as of version 1, before medal times were added.
+#[derive(Serialize, Deserialize)]
+struct LevelV1 {
+    name: String,
+    author: String,
+    dark_tilemap: TilemapV3,
+    light_tilemap: TilemapV3,
+    palette: Palette,
+    spawn_pos: Vec2<f32>,
+    end_pos: Vec2<f32>,
+}
+
+impl From<LevelV1> for LevelV2 {
+    fn from(level: LevelV1) -> LevelV2 {
+        LevelV2 {
+            name: level.name,
+            author: level.author,
+            dark_tilemap: level.dark_tilemap,
+            light_tilemap: level.light_tilemap,
+            palette: level.palette,
+            spawn_pos: level.spawn_pos,
+            end_pos: level.end_pos,
+            medals: MedalTimes::default(),
+        }
+    }
+}
+
+/// `Level` as of version 2, before entities were added.
+#[derive(Serialize, Deserialize)]
+struct LevelV2 {
+    name: String,
+    author: String,
+    dark_tilemap: TilemapV3,
+    light_tilemap: TilemapV3,
+    palette: Palette,
+    spawn_pos: Vec2<f32>,
+    end_pos: Vec2<f32>,
+    medals: MedalTimes,
+}
+
+impl From<LevelV2> for LevelV3 {
+    fn from(level: LevelV2) -> LevelV3 {
+        LevelV3 {
+            name: level.name,
+            author: level.author,
+            dark_tilemap: level.dark_tilemap,
+            light_tilemap: level.light_tilemap,
+            palette: level.palette,
+            spawn_pos: level.spawn_pos,
+            end_pos: level.end_pos,
+            medals: level.medals,
+            entities: Vec::new(),
+        }
+    }
+}
+
+/// `Level` as of version 3, before springs had their own force.
+///
+/// Unversioned RON files may predate medals and entities, which are defaulted like in `Level`.
+#[derive(Serialize, Deserialize)]
+struct LevelV3 {
+    name: String,
+    author: String,
+    dark_tilemap: TilemapV3,
+    light_tilemap: TilemapV3,
+    palette: Palette,
+    spawn_pos: Vec2<f32>,
+    end_pos: Vec2<f32>,
+    #[serde(default)]
+    medals: MedalTimes,
+    #[serde(default)]
+    entities: Vec<Entity>,
+}
+
+impl From<LevelV3> for Level {
+    fn from(level: LevelV3) -> Level {
+        Level {
+            name: level.name,
+            author: level.author,
+            dark_tilemap: level.dark_tilemap.into(),
+            light_tilemap: level.light_tilemap.into(),
+            palette: level.palette,
+            spawn_pos: level.spawn_pos,
+            end_pos: level.end_pos,
+            medals: level.medals,
+            entities: level.entities,
+        }
+    }
+}
+
+/// Deserializes a payload of the given format version and upgrades it to the current `Level`.
+///
+/// When the layout of `Level`, `Tilemap` or `Tile` changes in a way bincode or RON cannot read
+/// back, bump `FORMAT_VERSION`, keep a frozen copy of the previous types in this module and
+/// convert them here.
+fn migrate(version: u16, payload: &impl Payload) -> Result<Level, LevelError> {
+    match version {
+        // Version 0 is the headerless payload, which has the same layout as version 1
+        0 | 1 => payload
+            .read::<LevelV1>()
+            .map(|level| Level::from(LevelV3::from(LevelV2::from(level)))),
+        2 => payload
+            .read::<LevelV2>()
+            .map(|level| Level::from(LevelV3::from(level))),
+        3 => payload.read::<LevelV3>().map(Level::from),
+        4 => payload.read(),
+        found => Err(LevelError::NewerVersion {
+            found,
+            supported: FORMAT_VERSION,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bincode::Options;
+
+    use super::{
+        decode, decode_ron, encode, encode_ron, options, Header, LevelV1, LevelV3, TileV3,
+        TilemapV3, FORMAT_VERSION, MAGIC,
+    };
+    use crate::{
+        level::{Level, LevelError},
+        tilemap::{Facing, Tile, Tilemap},
+    };
+
+    fn legacy_tilemap(tilemap: &Tilemap) -> TilemapV3 {
+        let size = tilemap.size();
+        TilemapV3 {
+            tiles: vec![TileV3::None; size.x * size.y],
+            tilemap_size: size,
+            tile_size: tilemap.tile_size(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let level = Level::default();
+        let bytes = encode(&level).unwrap();
+        assert!(bytes.starts_with(&MAGIC));
+        let (header, _) = Header::parse(&bytes).unwrap();
+        assert_eq!(header, Header::current());
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(encode(&decoded).unwrap(), bytes);
+    }
+
+    #[test]
+    fn headerless_file_is_version_zero() {
+        let level = Level::default();
+        let legacy = options()
+            .serialize(&LevelV1 {
+                name: level.name.clone(),
+                author: level.author.clone(),
+                dark_tilemap: legacy_tilemap(&level.dark_tilemap),
+                light_tilemap: legacy_tilemap(&level.light_tilemap),
+                palette: level.palette,
+                spawn_pos: level.spawn_pos,
+                end_pos: level.end_pos,
+            })
+            .unwrap();
+        let (header, _) = Header::parse(&legacy).unwrap();
+        assert_eq!(header.version, 0);
+        let decoded = decode(&legacy).unwrap();
+        assert_eq!(encode(&decoded).unwrap(), encode(&level).unwrap());
+    }
+
+    #[test]
+    fn springs_get_the_default_force() {
+        let level = Level::default();
+        let mut dark_tilemap = legacy_tilemap(&level.dark_tilemap);
+        dark_tilemap.tiles[1] = TileV3::Spring(Facing::Left);
+        let payload = options()
+            .serialize(&LevelV3 {
+                name: level.name.clone(),
+                author: level.author.clone(),
+                dark_tilemap,
+                light_tilemap: legacy_tilemap(&level.light_tilemap),
+                palette: level.palette,
+                spawn_pos: level.spawn_pos,
+                end_pos: level.end_pos,
+                medals: level.medals,
+                entities: Vec::new(),
+            })
+            .unwrap();
+        let header = Header {
+            version: 3,
+            flags: 0,
+        };
+        let bytes = [&header.to_bytes()[..], &payload].concat();
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(
+            decoded.dark_tilemap.get_tile_usize((1, 0)),
+            Some(Tile::Spring(Facing::Left, Tile::DEFAULT_SPRING_FORCE))
+        );
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut bytes = encode(&Level::default()).unwrap();
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+        assert!(matches!(
+            decode(&bytes),
+            Err(LevelError::NewerVersion { found, supported })
+                if found == FORMAT_VERSION + 1 && supported == FORMAT_VERSION
+        ));
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        let level = Level::default();
+        let text = encode_ron(&level).unwrap();
+        assert!(text.contains(&format!("version: {}", FORMAT_VERSION)));
+        let decoded = decode_ron(text.as_bytes()).unwrap();
+        assert_eq!(encode(&decoded).unwrap(), encode(&level).unwrap());
+    }
+
+    #[test]
+    fn unversioned_ron_is_read() {
+        let level = Level::default();
+        let text = ron::ser::to_string(&level).unwrap();
+        let decoded = decode_ron(text.as_bytes()).unwrap();
+        assert_eq!(encode(&decoded).unwrap(), encode(&level).unwrap());
+    }
+
+    #[test]
+    fn unversioned_ron_springs_get_the_default_force() {
+        let level = Level::default();
+        let mut dark_tilemap = legacy_tilemap(&level.dark_tilemap);
+        dark_tilemap.tiles[1] = TileV3::Spring(Facing::Up);
+        let text = ron::ser::to_string(&LevelV3 {
+            name: level.name.clone(),
+            author: level.author.clone(),
+            dark_tilemap,
+            light_tilemap: legacy_tilemap(&level.light_tilemap),
+            palette: level.palette,
+            spawn_pos: level.spawn_pos,
+            end_pos: level.end_pos,
+            medals: level.medals,
+            entities: Vec::new(),
+        })
+        .unwrap();
+        assert!(text.contains("Spring(Up)"));
+        let decoded = decode_ron(text.as_bytes()).unwrap();
+        assert_eq!(
+            decoded.dark_tilemap.get_tile_usize((1, 0)),
+            Some(Tile::Spring(Facing::Up, Tile::DEFAULT_SPRING_FORCE))
+        );
+    }
+
+    #[test]
+    fn newer_ron_version_is_rejected() {
+        let text = encode_ron(&Level::default()).unwrap().replace(
+            &format!("version: {}", FORMAT_VERSION),
+            &format!("version: {}", FORMAT_VERSION + 1),
+        );
+        assert!(matches!(
+            decode_ron(text.as_bytes()),
+            Err(LevelError::NewerVersion { found, .. }) if found == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn invalid_ron_is_rejected() {
+        let text = encode_ron(&Level::default()).unwrap();
+        let truncated = &text[..text.len() / 2];
+        assert!(matches!(
+            decode_ron(truncated.as_bytes()),
+            Err(LevelError::RonDeserialization(_))
+        ));
+        assert!(matches!(
+            decode_ron(b"(version: 4, level: ())"),
+            Err(LevelError::RonDeserialization(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        let mut bytes = encode(&Level::default()).unwrap();
+        bytes[6..8].copy_from_slice(&0x0101_u16.to_be_bytes());
+        assert!(matches!(
+            Header::parse(&bytes),
+            Err(LevelError::UnknownFlags(0x0101))
+        ));
+        assert!(decode(&bytes).is_err());
+    }
+}
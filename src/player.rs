@@ -1,15 +1,25 @@
-use tetra::{
-    graphics::Rectangle,
-    input::{self, Key},
-    math::Vec2,
-};
+use std::hash::Hash;
+
+use tetra::{graphics::Rectangle, math::Vec2};
 
 use crate::{
+    input::InputFrame,
     tilemap::{Axis, Facing},
     world::WorldMode,
 };
 
-#[derive(Debug)]
+/// Ground the player can stand on, changing how they walk.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Surface {
+    #[default]
+    Normal,
+    /// Slippery ground, slow to speed up and slow down on.
+    Ice,
+    /// Ground moving sideways at this speed, like conveyors.
+    Moving(f32),
+}
+
+#[derive(Debug, Clone)]
 pub struct Player {
     position: Vec2<f32>,
     velocity: Vec2<f32>,
@@ -20,6 +30,10 @@ pub struct Player {
     flip_horizontal: bool,
     flip_vertical: bool,
     hit_spring: bool,
+    /// Ticks left before the player can steer again after a sideways spring.
+    launch_ticks: u32,
+    /// Ground stood on during the last tick, found again while solving collisions.
+    ground: Option<Surface>,
 }
 
 impl Player {
@@ -27,7 +41,8 @@ impl Player {
     pub const HALF_SIZE: Vec2<f32> = Vec2::new(Self::PLAYER_SQUARE / 2., Self::PLAYER_SQUARE / 2.);
     pub const FALL_DOWN: f32 = 1.;
     pub const FALL_UP: f32 = -1.;
-    pub const SPRING_FORCE: f32 = 10.;
+    /// Ticks without air control after being launched by a sideways spring.
+    pub const LAUNCH_TICKS: u32 = 20;
 
     pub fn new(spawn_pos: Vec2<f32>) -> Player {
         Player {
@@ -40,21 +55,27 @@ impl Player {
             flip_horizontal: false,
             flip_vertical: false,
             hit_spring: false,
+            launch_ticks: 0,
+            ground: None,
         }
     }
 
-    pub fn update(&mut self, ctx: &mut tetra::Context) {
+    pub fn update(&mut self, input: &InputFrame) {
         self.portal_traversed = false;
         const MAX_FALL_SPEED: f32 = 6.5;
         const GRAVITY: f32 = 0.3;
         const JUMP_FORCE: f32 = 5.5;
         const WALK_SPEED: f32 = 4.;
         const WALK_ACCELERATION: f32 = 0.75;
+        const ICE_ACCELERATION: f32 = 0.05;
 
-        let left = input::is_key_down(ctx, Key::Left);
-        let right = input::is_key_down(ctx, Key::Right);
-        let jump = input::is_key_pressed(ctx, Key::Space);
-        let jumping = input::is_key_down(ctx, Key::Space);
+        let InputFrame {
+            left,
+            right,
+            jump,
+            jump_held: jumping,
+            ..
+        } = *input;
 
         let mut target_speed = 0.;
         if left {
@@ -66,11 +87,22 @@ impl Player {
         if left == right {
             target_speed = 0.;
         }
+        // Walking is relative to the ground, and air control works like normal ground
+        let (acceleration, surface_velocity) = match self.ground.take() {
+            Some(Surface::Ice) => (ICE_ACCELERATION, 0.),
+            Some(Surface::Moving(speed)) => (WALK_ACCELERATION, speed),
+            Some(Surface::Normal) | None => (WALK_ACCELERATION, 0.),
+        };
+        target_speed += surface_velocity;
 
-        let direction = (target_speed - self.velocity.x).signum();
-        self.velocity.x += WALK_ACCELERATION * direction;
-        if (target_speed - self.velocity.x).signum() != direction {
-            self.velocity.x = target_speed;
+        if self.launch_ticks > 0 {
+            self.launch_ticks -= 1;
+        } else {
+            let direction = (target_speed - self.velocity.x).signum();
+            self.velocity.x += acceleration * direction;
+            if (target_speed - self.velocity.x).signum() != direction {
+                self.velocity.x = target_speed;
+            }
         }
 
         if self.hit_spring && self.velocity.y.signum() == self.fall_direction.signum() {
@@ -111,43 +143,55 @@ impl Player {
         }
     }
 
+    /// Each world has its own gravity, which applies when entering it.
     pub fn on_world_change(&mut self, mode: WorldMode) {
         match mode {
-            WorldMode::Dark => {
-                self.fall_direction = Self::FALL_DOWN;
-                self.flip_vertical = false;
-            }
-            WorldMode::Light => {
-                self.fall_direction = Self::FALL_UP;
-                self.flip_vertical = true;
-            }
+            WorldMode::Dark => self.set_fall_direction(Self::FALL_DOWN),
+            WorldMode::Light => self.set_fall_direction(Self::FALL_UP),
         }
     }
 
-    fn on_land(&mut self) {
+    pub fn fall_direction(&self) -> f32 {
+        self.fall_direction
+    }
+
+    /// Sets which way the player falls, either `FALL_DOWN` or `FALL_UP`.
+    pub fn set_fall_direction(&mut self, direction: f32) {
+        self.fall_direction = direction;
+        self.flip_vertical = direction == Self::FALL_UP;
+    }
+
+    fn on_land(&mut self, surface: Surface) {
         self.can_jump = true;
+        self.ground = Some(surface);
     }
 
-    pub fn on_spring(&mut self, facing: Facing) {
-        if self.hit_spring {
-            return;
-        }
+    pub fn on_spring(&mut self, facing: Facing, force: f32) {
         match facing {
-            Facing::Up => {
-                self.velocity.y = -Self::SPRING_FORCE;
-            }
-            Facing::Down => {
-                self.velocity.y = Self::SPRING_FORCE;
+            Facing::Up | Facing::Down => {
+                if self.hit_spring {
+                    return;
+                }
+                self.velocity.y = if facing == Facing::Up { -force } else { force };
+                self.hit_spring = true;
+                self.can_jump = true;
             }
-            _ => {
-                return;
+            Facing::Left | Facing::Right => {
+                if self.launch_ticks > 0 {
+                    return;
+                }
+                self.velocity.x = if facing == Facing::Left {
+                    -force
+                } else {
+                    force
+                };
+                self.launch_ticks = Self::LAUNCH_TICKS;
             }
         }
-        self.hit_spring = true;
-        self.can_jump = true;
     }
 
-    pub fn solve_collision_y(&mut self, rect: &Rectangle) {
+    /// Collides vertically with `rect`, standing on it as `surface` when landing on it.
+    pub fn solve_collision_y(&mut self, rect: &Rectangle, surface: Surface) {
         let next_hbox = Rectangle::new(
             self.position.x,
             self.position.y + self.velocity.y,
@@ -158,13 +202,13 @@ impl Player {
             if self.velocity.y < 0. {
                 // Top collision
                 if self.fall_direction == Self::FALL_UP {
-                    self.on_land();
+                    self.on_land(surface);
                 }
                 self.position.y = rect.y + rect.height;
             }
             if self.velocity.y > 0. {
                 if self.fall_direction == Self::FALL_DOWN {
-                    self.on_land();
+                    self.on_land(surface);
                 }
                 self.position.y = rect.y - Self::PLAYER_SQUARE;
             }
@@ -172,6 +216,49 @@ impl Player {
         }
     }
 
+    /// Whether the player is moving onto `rect` following gravity, meaning they stand on it.
+    pub fn is_landing_on(&self, rect: &Rectangle) -> bool {
+        let next_hbox = Rectangle::new(
+            self.position.x,
+            self.position.y + self.velocity.y,
+            Self::PLAYER_SQUARE,
+            Self::PLAYER_SQUARE,
+        );
+        self.velocity.y.signum() == self.fall_direction.signum() && rect.intersects(&next_hbox)
+    }
+
+    /// Collides with a one-way platform, which only stops the player coming from the side it
+    /// faces.
+    ///
+    /// Platforms facing up or down are floors for one gravity: they only stop a player falling
+    /// onto them, so floors face up when falling down and down when falling up. A player moving
+    /// against gravity, like after a spring, goes through. Sideways platforms don't depend on
+    /// gravity.
+    pub fn solve_one_way_collision(&mut self, rect: &Rectangle, facing: Facing) {
+        let hbox = self.get_hbox();
+        let from_facing_side = match facing {
+            Facing::Up => {
+                self.fall_direction == Self::FALL_DOWN
+                    && hbox.bottom() <= rect.top()
+                    && self.velocity.y > 0.
+            }
+            Facing::Down => {
+                self.fall_direction == Self::FALL_UP
+                    && hbox.top() >= rect.bottom()
+                    && self.velocity.y < 0.
+            }
+            Facing::Left => hbox.right() <= rect.left() && self.velocity.x > 0.,
+            Facing::Right => hbox.left() >= rect.right() && self.velocity.x < 0.,
+        };
+        if !from_facing_side {
+            return;
+        }
+        match facing {
+            Facing::Up | Facing::Down => self.solve_collision_y(rect, Surface::Normal),
+            Facing::Left | Facing::Right => self.solve_collision_x(rect),
+        }
+    }
+
     pub fn solve_collision_x(&mut self, rect: &Rectangle) {
         let next_hbox = Rectangle::new(
             self.position.x + self.velocity.x,
@@ -223,10 +310,37 @@ impl Player {
         )
     }
 
+    /// Moves the player along with the platform they stand on.
+    pub fn carry(&mut self, offset: Vec2<f32>) {
+        self.position += offset;
+    }
+
     pub fn post_update(&mut self) {
         self.position += self.velocity;
     }
 
+    /// Coarse copy of the player's state: two nearly identical players give the same result.
+    pub fn quantized_state(&self) -> impl Hash + Eq {
+        (
+            (self.position.x * 2.).round() as i32,
+            (self.position.y * 2.).round() as i32,
+            (self.velocity.x * 10.).round() as i32,
+            (self.velocity.y * 10.).round() as i32,
+            [
+                self.can_jump,
+                self.is_jumping,
+                self.hit_spring,
+                self.fall_direction == Self::FALL_UP,
+            ],
+            self.launch_ticks,
+            self.ground.map(|ground| match ground {
+                Surface::Normal => (0, 0),
+                Surface::Ice => (1, 0),
+                Surface::Moving(speed) => (2, (speed * 10.).round() as i32),
+            }),
+        )
+    }
+
     pub fn flip_horizontal(&self) -> bool {
         self.flip_horizontal
     }
@@ -0,0 +1,172 @@
+use std::{fs, io, path::Path};
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+use crate::world::World;
+use crate::{
+    input::InputFrame,
+    level::{Level, LevelError},
+    migration,
+};
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Serialization(Box<bincode::ErrorKind>),
+    Deserialization(Box<bincode::ErrorKind>),
+    InvalidHeader,
+    Level(LevelError),
+    LevelMismatch { expected: u64, found: u64 },
+}
+
+/// Every input of a run, one per tick, along with the hash of the level it was played on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Replay {
+    pub level_hash: u64,
+    pub inputs: Vec<InputFrame>,
+}
+
+impl Replay {
+    pub const EXTENSION: &'static str = "uprx";
+    const MAGIC: [u8; 4] = *b"UPRX";
+
+    pub fn new(level: &Level) -> Result<Replay, ReplayError> {
+        Ok(Replay {
+            level_hash: level.content_hash().map_err(ReplayError::Level)?,
+            inputs: Vec::new(),
+        })
+    }
+
+    pub fn record(&mut self, input: &InputFrame) {
+        self.inputs.push(*input);
+    }
+
+    pub fn check_level(&self, level: &Level) -> Result<(), ReplayError> {
+        let found = level.content_hash().map_err(ReplayError::Level)?;
+        if found != self.level_hash {
+            return Err(ReplayError::LevelMismatch {
+                expected: self.level_hash,
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    /// Plays every recorded input on a fresh world, returning the world as the replay left it.
+    #[cfg(test)]
+    pub fn play(&self, level: &Level) -> Result<World, ReplayError> {
+        self.check_level(level)?;
+        let mut world = World::new(level.clone());
+        for input in &self.inputs {
+            world.step(input, World::TICK);
+        }
+        Ok(world)
+    }
+
+    pub fn load_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        let payload = bytes
+            .strip_prefix(&Self::MAGIC)
+            .ok_or(ReplayError::InvalidHeader)?;
+        migration::options()
+            .deserialize(payload)
+            .map_err(ReplayError::Deserialization)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ReplayError> {
+        let payload = migration::options()
+            .serialize(self)
+            .map_err(ReplayError::Serialization)?;
+        Ok([&Self::MAGIC[..], &payload].concat())
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+        let bytes = fs::read(path).map_err(ReplayError::Io)?;
+        Self::load_bytes(&bytes)
+    }
+
+    pub fn save_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ReplayError> {
+        let bytes = self.to_bytes()?;
+        fs::write(path, bytes).map_err(ReplayError::Io)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Replay, ReplayError};
+    use crate::{input::InputFrame, level::Level};
+
+    const RIGHT: InputFrame = InputFrame {
+        left: false,
+        right: true,
+        jump: false,
+        jump_held: false,
+        reset: false,
+    };
+    const JUMP_RIGHT: InputFrame = InputFrame {
+        jump: true,
+        jump_held: true,
+        ..RIGHT
+    };
+    const HOLD_JUMP_RIGHT: InputFrame = InputFrame {
+        jump_held: true,
+        ..RIGHT
+    };
+
+    /// A run completing `level()`, as ticks each input is held for. It is written down rather
+    /// than solved for, so changes to the player's physics that break old runs fail the test.
+    const KNOWN_RUN: [(usize, InputFrame); 4] = [
+        (4, RIGHT),
+        (1, JUMP_RIGHT),
+        (10, HOLD_JUMP_RIGHT),
+        (20, RIGHT),
+    ];
+
+    fn level() -> Level {
+        Level::from_ascii(
+            "
+            spawn: 1 3
+            door: 7 3
+            dark:
+            #########
+            #.......#
+            #...k...#
+            #..###..#
+            #########
+            light:
+            .........
+            .........
+            .........
+            .........
+            .........
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn replay_reproduces_run() {
+        let level = level();
+        let mut replay = Replay::new(&level).unwrap();
+        for (ticks, input) in KNOWN_RUN {
+            (0..ticks).for_each(|_| replay.record(&input));
+        }
+
+        let bytes = replay.to_bytes().unwrap();
+        let loaded = Replay::load_bytes(&bytes).unwrap();
+        assert_eq!(loaded, replay);
+        assert!(loaded.play(&level).unwrap().win());
+    }
+
+    #[test]
+    fn replay_rejects_other_level() {
+        let replay = Replay::new(&level()).unwrap();
+        let mut other = level();
+        other.name = "Other".into();
+        assert!(matches!(
+            replay.play(&other),
+            Err(ReplayError::LevelMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    level::{LevelPack, UnlockRule},
+    replay::Replay,
+    splits::Splits,
+    util,
+};
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Serialization(ron::Error),
+    Deserialization(ron::error::SpannedError),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelProgress {
+    pub completed: bool,
+    /// Fastest completion, in world ticks.
+    pub best_ticks: Option<u64>,
+    pub deaths: u64,
+}
+
+impl LevelProgress {
+    /// Marks the level as completed, returning whether `ticks` is a new best time.
+    pub fn complete(&mut self, ticks: u64) -> bool {
+        self.completed = true;
+        match self.best_ticks {
+            Some(best) if best <= ticks => false,
+            _ => {
+                self.best_ticks = Some(ticks);
+                true
+            }
+        }
+    }
+}
+
+/// Progress in a single pack. Levels are keyed by their file name within the pack, so progress
+/// survives levels being reordered.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PackProgress {
+    pub levels: BTreeMap<String, LevelProgress>,
+    pub last_played: Option<String>,
+    /// Fastest run through the whole pack.
+    pub personal_best: Option<Splits>,
+    /// Runs started from the first level.
+    pub attempts: u32,
+}
+
+impl PackProgress {
+    pub fn level(&self, file: &str) -> Option<&LevelProgress> {
+        self.levels.get(file)
+    }
+
+    pub fn level_mut(&mut self, file: &str) -> &mut LevelProgress {
+        self.levels.entry(file.into()).or_default()
+    }
+
+    pub fn is_completed(&self, file: &str) -> bool {
+        self.level(file).is_some_and(|level| level.completed)
+    }
+
+    pub fn completed_count(&self, pack: &LevelPack) -> usize {
+        pack.level_files
+            .iter()
+            .filter(|file| self.is_completed(file))
+            .count()
+    }
+
+    /// Whether the level at `index` can be played, following the pack's unlock rule.
+    pub fn is_unlocked(&self, pack: &LevelPack, index: usize) -> bool {
+        let skips = match pack.unlock {
+            UnlockRule::Open => return true,
+            UnlockRule::Sequential => 0,
+            UnlockRule::Skips(skips) => skips,
+        };
+        let uncompleted = pack.level_files[..index]
+            .iter()
+            .filter(|file| !self.is_completed(file))
+            .count();
+        uncompleted <= skips
+    }
+
+    /// Index of the level to resume the pack from, if it is still part of the pack.
+    pub fn continue_index(&self, pack: &LevelPack) -> Option<usize> {
+        let last_played = self.last_played.as_ref()?;
+        pack.level_files.iter().position(|file| file == last_played)
+    }
+}
+
+/// Everything remembered between sessions, stored as RON in the saves directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    pub packs: BTreeMap<String, PackProgress>,
+}
+
+impl SaveData {
+    pub const DIRECTORY: &'static str = "saves";
+    const FILE_NAME: &'static str = "progress.ron";
+    const GHOSTS_DIRECTORY: &'static str = "ghosts";
+
+    fn path() -> PathBuf {
+        Path::new(Self::DIRECTORY).join(Self::FILE_NAME)
+    }
+
+    /// Loads the save file, starting over when there is none or it can't be read.
+    pub fn load() -> SaveData {
+        let bytes = match fs::read(Self::path()) {
+            Ok(bytes) => bytes,
+            Err(_) => return SaveData::default(),
+        };
+        match Self::load_bytes(&bytes) {
+            Ok(save) => save,
+            Err(e) => {
+                println!("Error loading save data: {:?}", e);
+                SaveData::default()
+            }
+        }
+    }
+
+    pub fn load_bytes(bytes: &[u8]) -> Result<SaveData, SaveError> {
+        ron::de::from_bytes(bytes).map_err(SaveError::Deserialization)
+    }
+
+    pub fn to_ron_string(&self) -> Result<String, SaveError> {
+        ron::ser::to_string_pretty(self, PrettyConfig::default()).map_err(SaveError::Serialization)
+    }
+
+    pub fn save(&self) -> Result<(), SaveError> {
+        let text = self.to_ron_string()?;
+        fs::create_dir_all(Self::DIRECTORY).map_err(SaveError::Io)?;
+        fs::write(Self::path(), text).map_err(SaveError::Io)
+    }
+
+    pub fn pack(&self, id: &str) -> Option<&PackProgress> {
+        self.packs.get(id)
+    }
+
+    pub fn pack_mut(&mut self, id: &str) -> &mut PackProgress {
+        self.packs.entry(id.into()).or_default()
+    }
+
+    /// Where the best run of a level is kept, to be played back as a ghost.
+    pub fn ghost_path(pack_id: &str, level_file: &str) -> PathBuf {
+        Path::new(Self::DIRECTORY)
+            .join(Self::GHOSTS_DIRECTORY)
+            .join(util::sanitize_file_name(pack_id))
+            .join(format!(
+                "{}.{}",
+                util::sanitize_file_name(level_file),
+                Replay::EXTENSION
+            ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LevelProgress, PackProgress, SaveData};
+    use crate::level::{LevelPack, UnlockRule};
+
+    #[test]
+    fn best_time_only_improves() {
+        let mut level = LevelProgress::default();
+        assert!(level.complete(300));
+        assert!(!level.complete(400));
+        assert!(level.complete(250));
+        assert_eq!(level.best_ticks, Some(250));
+        assert!(level.completed);
+    }
+
+    #[test]
+    fn unlock_rules() {
+        let mut pack = LevelPack {
+            level_files: vec!["a".into(), "b".into(), "c".into()],
+            ..Default::default()
+        };
+        let mut progress = PackProgress::default();
+        progress.level_mut("a").complete(60);
+        let unlocked = |pack: &LevelPack| {
+            (0..3)
+                .map(|i| progress.is_unlocked(pack, i))
+                .collect::<Vec<_>>()
+        };
+
+        pack.unlock = UnlockRule::Sequential;
+        assert_eq!(unlocked(&pack), [true, true, false]);
+        pack.unlock = UnlockRule::Skips(1);
+        assert_eq!(unlocked(&pack), [true, true, true]);
+        pack.unlock = UnlockRule::Open;
+        assert_eq!(unlocked(&pack), [true, true, true]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut save = SaveData::default();
+        let pack = save.pack_mut("my-pack");
+        pack.level_mut("intro.umdx").complete(120);
+        pack.level_mut("spikes.ron").deaths += 3;
+        pack.last_played = Some("spikes.ron".into());
+
+        let text = save.to_ron_string().unwrap();
+        assert_eq!(SaveData::load_bytes(text.as_bytes()).unwrap(), save);
+        assert_eq!(
+            SaveData::load_bytes(b"()").unwrap(),
+            SaveData::default(),
+            "missing fields should default"
+        );
+    }
+}
@@ -1,18 +1,22 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use egui_tetra::egui::{self, CtxRef};
 use tetra::{
-    graphics::{self, Camera, Color, DrawParams},
+    graphics::{self, text::Text, Camera, Color, DrawParams},
     input::{self, Key},
     math::Vec2,
     Event,
 };
 
 use crate::{
-    level::Level,
+    entity::{Entity, EntityKind},
+    level::{Level, LevelFormat},
     palette::Palette,
-    tilemap::{Axis, Facing, Tile, Tilemap},
-    world::WorldMode,
+    replay::Replay,
+    solver::{Search, Solution, Solver},
+    tilemap::{Axis, Facing, GravityZone, KeyColor, Tile},
+    validate::Diagnostic,
+    world::{World, WorldMode},
     Assets,
 };
 
@@ -29,16 +33,65 @@ fn color_egui(ui: &mut egui::Ui, label: &str, color: &mut Color) {
     color.b = col_bytes[2];
 }
 
+fn medal_time_egui(ui: &mut egui::Ui, label: &str, time: &mut Option<u64>) {
+    let mut enabled = time.is_some();
+    let mut secs = time.map_or(0., World::ticks_to_secs);
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut enabled, label);
+        ui.add_enabled(
+            enabled,
+            egui::DragValue::new(&mut secs)
+                .speed(0.1)
+                .clamp_range(0.0..=3600.0)
+                .suffix(" s"),
+        );
+    });
+    *time = enabled.then(|| World::secs_to_ticks(secs));
+}
+
+fn tile_id_egui(ui: &mut egui::Ui, label: &str, id: &mut u8, max: u8) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::DragValue::new(id).speed(0.1).clamp_range(0..=max));
+    });
+}
+
+/// Conveyors only move sideways, so vertical facings fall back to right.
+fn conveyor_facing(facing: Facing) -> Facing {
+    match facing {
+        Facing::Left => Facing::Left,
+        _ => Facing::Right,
+    }
+}
+
 pub struct EditorScene {
     level: Level,
     world_mode: WorldMode,
     mouse_pos: Vec2<f32>,
     facing: Facing,
     axis: Axis,
+    key_color: KeyColor,
+    spring_force: u8,
+    conveyor_speed: u8,
+    switch_group: u8,
+    switch_both_worlds: bool,
+    block_solid: bool,
+    teleporter_pair: u8,
+    gravity_zone: GravityZone,
     tile: Tile,
+    /// Entity whose waypoints are placed with the mouse instead of tiles.
+    selected_entity: Option<usize>,
     camera: Camera,
     quit: bool,
     level_path: Option<PathBuf>,
+    solution: Option<Solution>,
+    /// Solver search running a few states every frame, until it finds a solution.
+    search: Option<Search>,
+    /// Problems found in the level, cleared whenever it is edited so it is validated again.
+    diagnostics: Option<Vec<Diagnostic>>,
+    replay: Option<Replay>,
+    /// Labels of switch groups and teleporter pairs, made once per id.
+    id_texts: HashMap<u8, Text>,
 }
 
 impl EditorScene {
@@ -48,37 +101,37 @@ impl EditorScene {
     pub const TILEMAP_MAX_Y: usize = 1000;
     pub const ZOOM_MIN: f32 = 1.0;
     pub const ZOOM_MAX: f32 = 8.0;
-    pub const DEFAULT_LEVEL_NAME: &'static str = "Untitled Level";
-    pub const DEFAULT_AUTHOR_NAME: &'static str = "Unnamed Mapmaker";
-    pub const DEFAULT_TILEMAP_SIZE: (usize, usize) = (80, 45);
-    pub const DEFAULT_TILE_SIZE: (f32, f32) = (16., 16.);
+    /// States the solver explores every frame, keeping the editor responsive while it runs.
+    const SOLVER_STATES_PER_FRAME: usize = 200;
 
     pub fn new(ctx: &mut tetra::Context) -> EditorScene {
         let mut camera = Camera::with_window_size(ctx);
         camera.position = camera.visible_rect().bottom_right();
         camera.update();
         EditorScene {
-            level: Self::default_level(),
+            level: Level::default(),
             world_mode: WorldMode::Dark,
             mouse_pos: Vec2::default(),
             facing: Facing::Up,
             axis: Axis::Horizontal,
+            key_color: KeyColor::default(),
+            spring_force: Tile::DEFAULT_SPRING_FORCE,
+            conveyor_speed: Tile::DEFAULT_CONVEYOR_SPEED,
+            switch_group: 0,
+            switch_both_worlds: false,
+            block_solid: true,
+            teleporter_pair: 0,
+            gravity_zone: GravityZone::default(),
             tile: Tile::Solid,
+            selected_entity: None,
             camera,
             quit: false,
             level_path: None,
-        }
-    }
-
-    pub fn default_level() -> Level {
-        Level {
-            name: Self::DEFAULT_LEVEL_NAME.to_string(),
-            author: Self::DEFAULT_AUTHOR_NAME.to_string(),
-            dark_tilemap: Tilemap::new(Self::DEFAULT_TILEMAP_SIZE, Self::DEFAULT_TILE_SIZE),
-            light_tilemap: Tilemap::new(Self::DEFAULT_TILEMAP_SIZE, Self::DEFAULT_TILE_SIZE),
-            palette: Palette::default(),
-            spawn_pos: Vec2::zero(),
-            end_pos: Vec2::zero(),
+            solution: None,
+            search: None,
+            diagnostics: None,
+            replay: None,
+            id_texts: HashMap::new(),
         }
     }
 
@@ -101,7 +154,22 @@ impl EditorScene {
             self.tile = Tile::Key;
         }
         if input::is_key_pressed(ctx, Key::Num5) {
-            self.tile = Tile::Spring(self.facing);
+            self.tile = Tile::Spring(self.facing, self.spring_force);
+        }
+        if input::is_key_pressed(ctx, Key::Num6) {
+            self.tile = Tile::Checkpoint;
+        }
+        if input::is_key_pressed(ctx, Key::Num7) {
+            self.tile = Tile::ColorKey(self.key_color);
+        }
+        if input::is_key_pressed(ctx, Key::Num8) {
+            self.tile = Tile::Lock(self.key_color);
+        }
+        if input::is_key_pressed(ctx, Key::Num9) {
+            self.tile = Tile::OneWay(self.facing);
+        }
+        if input::is_key_pressed(ctx, Key::Num0) {
+            self.tile = Tile::Crumbling;
         }
 
         if input::is_key_pressed(ctx, Key::Up) {
@@ -168,27 +236,77 @@ impl EditorScene {
             WorldMode::Dark => &mut self.level.dark_tilemap,
             WorldMode::Light => &mut self.level.light_tilemap,
         };
+        let mut edited = false;
 
-        if !shift && !ctrl && input::is_mouse_button_down(ctx, input::MouseButton::Left) {
-            tilemap.set_tile_f32(self.mouse_pos, self.tile);
-        }
+        if let Some(entity) = self
+            .selected_entity
+            .and_then(|i| self.level.entities.get_mut(i))
+        {
+            if !shift && !ctrl && input::is_mouse_button_pressed(ctx, input::MouseButton::Left) {
+                entity.waypoints.push(tilemap.snap(self.mouse_pos));
+                edited = true;
+            }
+
+            if !shift && !ctrl && input::is_mouse_button_pressed(ctx, input::MouseButton::Right) {
+                entity.waypoints.pop();
+                edited = true;
+            }
+        } else {
+            if !shift && !ctrl && input::is_mouse_button_down(ctx, input::MouseButton::Left) {
+                tilemap.set_tile_f32(self.mouse_pos, self.tile);
+                edited = true;
+            }
 
-        if !shift && !ctrl && input::is_mouse_button_down(ctx, input::MouseButton::Right) {
-            tilemap.set_tile_f32(self.mouse_pos, Tile::None);
+            if !shift && !ctrl && input::is_mouse_button_down(ctx, input::MouseButton::Right) {
+                tilemap.set_tile_f32(self.mouse_pos, Tile::None);
+                edited = true;
+            }
         }
 
         if !shift && !ctrl && input::is_mouse_button_down(ctx, input::MouseButton::Middle) {
             self.level.spawn_pos = tilemap.snap(self.mouse_pos);
+            edited = true;
         }
 
         if !shift && ctrl && input::is_mouse_button_down(ctx, input::MouseButton::Middle) {
             self.level.end_pos = tilemap.snap(self.mouse_pos);
+            edited = true;
+        }
+
+        if edited {
+            self.level_edited();
+        }
+    }
+
+    /// Marks the level as edited, so it is validated again and previous solver results are
+    /// dropped.
+    fn level_edited(&mut self) {
+        self.diagnostics = None;
+        self.solution = None;
+        self.search = None;
+    }
+
+    fn load_replay(&mut self) {
+        let file = rfd::FileDialog::new()
+            .add_filter("Upfall-RS Replay", &[Replay::EXTENSION])
+            .pick_file();
+        if let Some(file) = file {
+            let res = Replay::load_file(&file).and_then(|r| {
+                r.check_level(&self.level)?;
+                Ok(r)
+            });
+            match res {
+                Ok(r) => self.replay = Some(r),
+                Err(e) => println!("Error loading replay at {}: {:?}", file.display(), e),
+            }
         }
     }
 
     fn new_level(&mut self) {
-        self.level = Self::default_level();
+        self.level = Level::default();
         self.level_path = None;
+        self.selected_entity = None;
+        self.level_edited();
     }
 
     fn save_level(&mut self) {
@@ -203,10 +321,14 @@ impl EditorScene {
 
     fn save_level_as(&mut self) {
         let path = rfd::FileDialog::new()
-            .add_filter("Upfall-RS Map Data", &["umdx"])
+            .add_filter("Upfall-RS Map Data", &[LevelFormat::BINARY_EXTENSION])
+            .add_filter("Upfall-RS Text Map Data", &[LevelFormat::RON_EXTENSION])
+            .add_filter("Upfall-RS ASCII Map Data", &[LevelFormat::ASCII_EXTENSION])
             .save_file();
         if let Some(mut p) = path {
-            p.set_extension("umdx");
+            if LevelFormat::from_path(&p).is_none() {
+                p.set_extension(LevelFormat::Binary.extension());
+            }
             self.level_path = Some(p.clone());
             match self.level.save_file(&p) {
                 Ok(_) => {}
@@ -217,13 +339,22 @@ impl EditorScene {
 
     fn load_level(&mut self) {
         let file = rfd::FileDialog::new()
-            .add_filter("Upfall-RS Map Data", &["umdx"])
+            .add_filter(
+                "Upfall-RS Map Data",
+                &[
+                    LevelFormat::BINARY_EXTENSION,
+                    LevelFormat::RON_EXTENSION,
+                    LevelFormat::ASCII_EXTENSION,
+                ],
+            )
             .pick_file();
         if let Some(file) = file {
             match Level::load_file(&file) {
                 Ok(l) => {
                     self.level = l;
                     self.level_path = Some(file);
+                    self.selected_entity = None;
+                    self.level_edited();
                 }
                 Err(e) => println!("Error loading level at {}: {:?}", file.display(), e),
             }
@@ -256,6 +387,21 @@ impl Scene for EditorScene {
             self.mouse_update(ctx)
         }
 
+        if let Some(search) = &mut self.search {
+            if let Some(solution) = search.run(Self::SOLVER_STATES_PER_FRAME) {
+                self.solution = Some(solution);
+                self.search = None;
+            }
+        }
+
+        if let Some(replay) = self.replay.take() {
+            return Ok(Transition::Push(Box::new(GameScene::with_replay(
+                ctx,
+                self.level.clone(),
+                replay,
+            )?)));
+        }
+
         if !wants_keyboard && !wants_mouse && input::is_key_pressed(ctx, Key::Enter) {
             return Ok(Transition::Push(Box::new(GameScene::new(
                 ctx,
@@ -287,6 +433,9 @@ impl Scene for EditorScene {
                 if ui.button("Save As").clicked() {
                     self.save_level_as();
                 }
+                if ui.button("Play Replay").clicked() {
+                    self.load_replay();
+                }
             });
             ui.separator();
             ui.horizontal(|ui| {
@@ -310,8 +459,11 @@ impl Scene for EditorScene {
                         .speed(0.1)
                         .clamp_range(Self::TILEMAP_MIN_Y..=Self::TILEMAP_MAX_Y),
                 );
-                self.level.dark_tilemap.resize(tilemap_size);
-                self.level.light_tilemap.resize(tilemap_size);
+                if tilemap_size != self.level.dark_tilemap.size() {
+                    self.level.dark_tilemap.resize(tilemap_size);
+                    self.level.light_tilemap.resize(tilemap_size);
+                    self.level_edited();
+                }
             });
             ui.separator();
             ui.horizontal(|ui| {
@@ -338,10 +490,49 @@ impl Scene for EditorScene {
                     ui.selectable_value(&mut self.tile, Tile::Spike(self.facing), "Spike");
                     ui.selectable_value(&mut self.tile, Tile::Portal(self.axis), "Portal");
                     ui.selectable_value(&mut self.tile, Tile::Key, "Key");
-                    ui.selectable_value(&mut self.tile, Tile::Spring(self.facing), "Spring");
+                    ui.selectable_value(
+                        &mut self.tile,
+                        Tile::Spring(self.facing, self.spring_force),
+                        "Spring",
+                    );
+                    ui.selectable_value(&mut self.tile, Tile::Checkpoint, "Checkpoint");
+                    ui.selectable_value(
+                        &mut self.tile,
+                        Tile::ColorKey(self.key_color),
+                        "Colored Key",
+                    );
+                    ui.selectable_value(&mut self.tile, Tile::Lock(self.key_color), "Lock");
+                    ui.selectable_value(&mut self.tile, Tile::OneWay(self.facing), "One-Way");
+                    ui.selectable_value(&mut self.tile, Tile::Crumbling, "Crumbling");
+                    ui.selectable_value(
+                        &mut self.tile,
+                        Tile::Conveyor(conveyor_facing(self.facing), self.conveyor_speed),
+                        "Conveyor",
+                    );
+                    ui.selectable_value(
+                        &mut self.tile,
+                        Tile::Switch(self.switch_group, self.switch_both_worlds),
+                        "Switch",
+                    );
+                    ui.selectable_value(
+                        &mut self.tile,
+                        Tile::SwitchBlock(self.switch_group, self.block_solid),
+                        "Switch Block",
+                    );
+                    ui.selectable_value(
+                        &mut self.tile,
+                        Tile::Teleporter(self.teleporter_pair),
+                        "Teleporter",
+                    );
+                    ui.selectable_value(
+                        &mut self.tile,
+                        Tile::Gravity(self.gravity_zone),
+                        "Gravity Zone",
+                    );
+                    ui.selectable_value(&mut self.tile, Tile::Ice, "Ice");
                 });
             match self.tile {
-                Tile::Spike(ref mut facing) => {
+                Tile::Spike(ref mut facing) | Tile::OneWay(ref mut facing) => {
                     egui::ComboBox::from_label("Facing")
                         .selected_text(self.facing.to_string())
                         .show_ui(ui, |ui| {
@@ -361,14 +552,87 @@ impl Scene for EditorScene {
                         });
                     *axis = self.axis;
                 }
-                Tile::Spring(ref mut facing) => {
+                Tile::Spring(ref mut facing, ref mut force) => {
                     egui::ComboBox::from_label("Facing")
                         .selected_text(self.facing.to_string())
                         .show_ui(ui, |ui| {
                             ui.selectable_value(&mut self.facing, Facing::Up, "Up");
                             ui.selectable_value(&mut self.facing, Facing::Down, "Down");
+                            ui.selectable_value(&mut self.facing, Facing::Left, "Left");
+                            ui.selectable_value(&mut self.facing, Facing::Right, "Right");
                         });
                     *facing = self.facing;
+                    ui.horizontal(|ui| {
+                        ui.label("Force");
+                        ui.add(
+                            egui::DragValue::new(&mut self.spring_force)
+                                .speed(0.1)
+                                .clamp_range(1..=Tile::MAX_SPRING_FORCE),
+                        );
+                    });
+                    *force = self.spring_force;
+                }
+                Tile::Conveyor(ref mut facing, ref mut speed) => {
+                    let mut conveyor_facing = conveyor_facing(self.facing);
+                    egui::ComboBox::from_label("Direction")
+                        .selected_text(conveyor_facing.to_string())
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut conveyor_facing, Facing::Left, "Left");
+                            ui.selectable_value(&mut conveyor_facing, Facing::Right, "Right");
+                        });
+                    self.facing = conveyor_facing;
+                    *facing = conveyor_facing;
+                    ui.horizontal(|ui| {
+                        ui.label("Speed");
+                        ui.add(
+                            egui::DragValue::new(&mut self.conveyor_speed)
+                                .speed(0.2)
+                                .clamp_range(1..=Tile::MAX_CONVEYOR_SPEED)
+                                .suffix(" /10 px/tick"),
+                        );
+                    });
+                    *speed = self.conveyor_speed;
+                }
+                Tile::Switch(ref mut group, ref mut both_worlds) => {
+                    tile_id_egui(ui, "Group", &mut self.switch_group, Tile::MAX_SWITCH_GROUP);
+                    ui.checkbox(&mut self.switch_both_worlds, "Works in both worlds");
+                    *group = self.switch_group;
+                    *both_worlds = self.switch_both_worlds;
+                }
+                Tile::SwitchBlock(ref mut group, ref mut solid) => {
+                    tile_id_egui(ui, "Group", &mut self.switch_group, Tile::MAX_SWITCH_GROUP);
+                    ui.checkbox(&mut self.block_solid, "Solid at first");
+                    *group = self.switch_group;
+                    *solid = self.block_solid;
+                }
+                Tile::Teleporter(ref mut pair) => {
+                    tile_id_egui(
+                        ui,
+                        "Pair",
+                        &mut self.teleporter_pair,
+                        Tile::MAX_TELEPORTER_PAIR,
+                    );
+                    *pair = self.teleporter_pair;
+                }
+                Tile::Gravity(ref mut zone) => {
+                    egui::ComboBox::from_label("Gravity")
+                        .selected_text(self.gravity_zone.to_string())
+                        .show_ui(ui, |ui| {
+                            for kind in [GravityZone::Flip, GravityZone::Down, GravityZone::Up] {
+                                ui.selectable_value(&mut self.gravity_zone, kind, kind.to_string());
+                            }
+                        });
+                    *zone = self.gravity_zone;
+                }
+                Tile::ColorKey(ref mut key_color) | Tile::Lock(ref mut key_color) => {
+                    egui::ComboBox::from_label("Color")
+                        .selected_text(self.key_color.to_string())
+                        .show_ui(ui, |ui| {
+                            for color in KeyColor::ALL {
+                                ui.selectable_value(&mut self.key_color, color, color.to_string());
+                            }
+                        });
+                    *key_color = self.key_color;
                 }
                 _ => {}
             }
@@ -403,6 +667,97 @@ impl Scene for EditorScene {
                 _ => {}
             }
             ui.separator();
+            ui.label("Medal Times");
+            let medals = self.level.medals;
+            medal_time_egui(ui, "Gold", &mut self.level.medals.gold);
+            medal_time_egui(ui, "Silver", &mut self.level.medals.silver);
+            medal_time_egui(ui, "Bronze", &mut self.level.medals.bronze);
+            if self.level.medals != medals {
+                self.level_edited();
+            }
+            ui.separator();
+            ui.collapsing(format!("Entities ({})", self.level.entities.len()), |ui| {
+                ui.label("Click to add waypoints to the selected entity, right click to remove");
+                ui.horizontal(|ui| {
+                    for kind in [EntityKind::Platform, EntityKind::Saw] {
+                        if ui.button(format!("Add {}", kind)).clicked() {
+                            self.level.entities.push(Entity::new(kind, self.world_mode));
+                            self.selected_entity = Some(self.level.entities.len() - 1);
+                            self.level_edited();
+                        }
+                    }
+                });
+                let mut removed = None;
+                let mut speed_changed = false;
+                for (i, entity) in self.level.entities.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        let selected = self.selected_entity == Some(i);
+                        let label = format!(
+                            "{} {} ({} waypoints)",
+                            entity.world,
+                            entity.kind,
+                            entity.waypoints.len()
+                        );
+                        if ui.selectable_label(selected, label).clicked() {
+                            self.selected_entity = (!selected).then_some(i);
+                        }
+                        let speed = entity.speed;
+                        ui.add(
+                            egui::DragValue::new(&mut entity.speed)
+                                .speed(0.05)
+                                .clamp_range(0.0..=16.0)
+                                .suffix(" px/tick"),
+                        );
+                        speed_changed |= entity.speed != speed;
+                        if ui.button("Remove").clicked() {
+                            removed = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = removed {
+                    self.level.entities.remove(i);
+                    self.selected_entity = None;
+                    self.level_edited();
+                } else if speed_changed {
+                    self.level_edited();
+                }
+            });
+            ui.separator();
+            let level = &self.level;
+            let diagnostics = self.diagnostics.get_or_insert_with(|| level.validate());
+            ui.collapsing(format!("Problems ({})", diagnostics.len()), |ui| {
+                if diagnostics.is_empty() {
+                    ui.label("No problems found");
+                }
+                for diagnostic in diagnostics.iter() {
+                    ui.label(diagnostic.to_string());
+                }
+                ui.horizontal(|ui| {
+                    if let Some(search) = &self.search {
+                        if ui.button("Cancel").clicked() {
+                            self.search = None;
+                        } else {
+                            ui.label(format!("Searching, {} states so far", search.explored()));
+                        }
+                    } else if ui.button("Check completable").clicked() {
+                        self.solution = None;
+                        self.search = Some(Solver::new().start(&self.level));
+                    }
+                    match &self.solution {
+                        Some(Solution::Solved(inputs)) => {
+                            ui.label(format!("Completable in {} frames", inputs.len()));
+                        }
+                        Some(Solution::NotFound) => {
+                            ui.label("No solution found within limits");
+                        }
+                        Some(Solution::Inconclusive) => {
+                            ui.label("No solution found within limits, the level is too big");
+                        }
+                        None => {}
+                    }
+                });
+            });
+            ui.separator();
             if ui.button("Quit Editor").clicked() {
                 self.quit = true;
             }
@@ -431,6 +786,79 @@ impl Scene for EditorScene {
         self.level
             .light_tilemap
             .render_tilemap(ctx, assets, Color::BLACK.with_alpha(light_alpha));
+        let tile_size = self.level.dark_tilemap.tile_size();
+        let (font_size, font) = &assets.pixel_font_small;
+        let text_scale = Vec2::one() * tile_size.y / *font_size * 0.75;
+        // Group and pair ids stand out from the tiles of both worlds
+        let id_color = Color::rgb8(255, 215, 0);
+        let mut teleporters = vec![];
+        let id_texts = &mut self.id_texts;
+        for (tilemap, alpha) in [
+            (&self.level.dark_tilemap, dark_alpha),
+            (&self.level.light_tilemap, light_alpha),
+        ] {
+            tilemap.run_for_each_tile(|(x, y), tile| {
+                let id = match *tile {
+                    Tile::Switch(group, _) | Tile::SwitchBlock(group, _) => group,
+                    Tile::Teleporter(pair) => {
+                        teleporters.push((Vec2::new(x as f32, y as f32), pair, alpha));
+                        pair
+                    }
+                    _ => return,
+                };
+                let pos = Vec2::new(x as f32, y as f32) * tile_size;
+                let text = id_texts
+                    .entry(id)
+                    .or_insert_with(|| Text::new(id.to_string(), font.clone()));
+                text.draw(
+                    ctx,
+                    DrawParams::new()
+                        .position(pos + tile_size / 8.)
+                        .scale(text_scale)
+                        .color(id_color.with_alpha(alpha)),
+                );
+            });
+        }
+        // Dotted lines between linked teleporters
+        for (i, (from, pair, alpha)) in teleporters.iter().enumerate() {
+            for (to, _, other_alpha) in teleporters[i + 1..].iter().filter(|t| t.1 == *pair) {
+                let start = (*from + 0.5) * tile_size;
+                let end = (*to + 0.5) * tile_size;
+                let dots = (start.distance(end) / 4.) as usize;
+                let color = id_color.with_alpha(alpha.max(*other_alpha) / 2.);
+                for dot in 0..dots {
+                    let pos = Vec2::lerp(start, end, dot as f32 / dots as f32);
+                    assets.pixel.draw(
+                        ctx,
+                        DrawParams::new()
+                            .position(pos - 0.5)
+                            .scale(Vec2::one())
+                            .color(color),
+                    );
+                }
+            }
+        }
+        for (i, entity) in self.level.entities.iter().enumerate() {
+            let color = match entity.world {
+                WorldMode::Dark => Color::WHITE.with_alpha(dark_alpha),
+                WorldMode::Light => Color::BLACK.with_alpha(light_alpha),
+            };
+            entity.draw(ctx, assets, entity.position(0), tile_size, color, 0.);
+            let marker = if self.selected_entity == Some(i) {
+                id_color
+            } else {
+                color.with_alpha(0.5)
+            };
+            for waypoint in &entity.waypoints {
+                assets.pixel.draw(
+                    ctx,
+                    DrawParams::new()
+                        .position(*waypoint + tile_size * 3. / 8.)
+                        .scale(tile_size / 4.)
+                        .color(marker),
+                );
+            }
+        }
         assets.player.draw(ctx, self.level.spawn_pos);
         assets.door.draw(ctx, self.level.end_pos);
         if self
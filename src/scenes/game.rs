@@ -1,3 +1,9 @@
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 use egui_tetra::egui::CtxRef;
 use tetra::{
     graphics::{
@@ -12,8 +18,15 @@ use tetra::{
 };
 
 use crate::{
+    ghost::Ghost,
+    input::InputFrame,
     level::{Level, LevelPack},
+    medal::MedalTimes,
     palette::PaletteSystem,
+    replay::{Replay, ReplayError},
+    save::SaveData,
+    splits::{self, Splits},
+    util,
     world::World,
     Assets, Scene,
 };
@@ -161,6 +174,121 @@ impl LevelLabel {
     }
 }
 
+/// Run time and difference to the personal best, in the top left corner.
+#[derive(Default)]
+struct TimerLabel {
+    time_text: Option<Text>,
+    delta_text: Option<Text>,
+}
+
+impl TimerLabel {
+    const POSITION: Vec2<f32> = Vec2::new(10., 10.);
+    const LINE_HEIGHT: f32 = 25.;
+
+    pub fn draw(
+        &mut self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        ticks: u64,
+        delta: Option<i64>,
+        dark_color: &Color,
+        light_color: &Color,
+    ) {
+        let font = &assets.pixel_font_small.1;
+        let time_text = self
+            .time_text
+            .get_or_insert_with(|| Text::new("", font.clone()));
+        time_text.set_content(util::format_time(World::ticks_to_secs(ticks)));
+        LevelLabel::draw_text(
+            ctx,
+            time_text,
+            Self::POSITION,
+            Vec2::zero(),
+            LevelLabel::STROKE_WIDTH,
+            *light_color,
+            *dark_color,
+        );
+        if let Some(delta) = delta {
+            let delta_text = self
+                .delta_text
+                .get_or_insert_with(|| Text::new("", font.clone()));
+            delta_text.set_content(splits::format_delta(delta));
+            LevelLabel::draw_text(
+                ctx,
+                delta_text,
+                Self::POSITION + Vec2::new(0., Self::LINE_HEIGHT),
+                Vec2::zero(),
+                LevelLabel::STROKE_WIDTH,
+                *light_color,
+                *dark_color,
+            );
+        }
+    }
+}
+
+/// Medal earned on the last completed level, shown for a few seconds at the top of the screen.
+#[derive(Default)]
+struct MedalLabel {
+    message: String,
+    text: Option<Text>,
+    timer: f32,
+    new_text: bool,
+}
+
+impl MedalLabel {
+    const SHOW_TIME: f32 = 3.;
+    const POSITION_Y: f32 = 40.;
+
+    pub fn set(&mut self, medals: &MedalTimes, ticks: u64) {
+        if medals.is_empty() {
+            return;
+        }
+        let time = util::format_time(World::ticks_to_secs(ticks));
+        self.message = match medals.medal(ticks) {
+            Some(medal) => format!("{} medal! {}", medal, time),
+            None => format!("No medal, {}", time),
+        };
+        self.timer = 0.;
+        self.new_text = true;
+    }
+
+    pub fn update_timer(&mut self, dt: f32) {
+        self.timer += dt;
+    }
+
+    pub fn draw(
+        &mut self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        dark_color: &Color,
+        light_color: &Color,
+    ) {
+        if self.new_text {
+            self.text = Some(Text::new(&self.message, assets.pixel_font.1.clone()));
+            self.new_text = false;
+        }
+        let Some(text) = &mut self.text else {
+            return;
+        };
+        if self.timer >= Self::SHOW_TIME {
+            return;
+        }
+        let Some(bounds) = text.get_bounds(ctx) else {
+            return;
+        };
+        let (width, _) = window::get_size(ctx);
+        LevelLabel::draw_text(
+            ctx,
+            text,
+            Vec2::new(width as f32 / 2., Self::POSITION_Y),
+            Vec2::new(bounds.center().x, bounds.top()),
+            LevelLabel::STROKE_WIDTH,
+            *light_color,
+            *dark_color,
+        );
+    }
+}
+
 pub struct GameScene {
     world: World,
     camera: Camera,
@@ -170,10 +298,29 @@ pub struct GameScene {
     current_level: usize,
     playtest: bool,
     label: LevelLabel,
+    accumulator: f32,
+    pending_input: InputFrame,
+    recording: Option<Replay>,
+    playback: Option<(Replay, usize)>,
+    best_runs: Vec<Option<Replay>>,
+    ghost: Option<Ghost>,
+    save: SaveData,
+    /// Pack progress is saved under, `None` when playtesting or watching a replay.
+    pack_id: Option<String>,
+    saved_deaths: usize,
+    /// Splits of the run through the pack, only kept for runs started from the first level.
+    run: Option<Splits>,
+    personal_best: Option<Splits>,
+    segment_ticks: u64,
+    last_delta: Option<i64>,
+    timer: TimerLabel,
+    medal_label: MedalLabel,
 }
 
 impl GameScene {
     const INNER_SIZE: Vec2<i32> = Vec2::new(640, 360);
+    const MAX_TICKS_PER_FRAME: usize = 5;
+    const REPLAYS_DIRECTORY: &'static str = "replays";
     pub fn new(ctx: &mut tetra::Context, level: Level) -> tetra::Result<GameScene> {
         let mut scene = GameScene::with_pack(
             ctx,
@@ -183,14 +330,62 @@ impl GameScene {
             },
         )?;
         scene.playtest = false;
+        scene.pack_id = None;
+        Ok(scene)
+    }
+
+    pub fn with_replay(
+        ctx: &mut tetra::Context,
+        level: Level,
+        replay: Replay,
+    ) -> tetra::Result<GameScene> {
+        let mut scene = GameScene::new(ctx, level)?;
+        scene.recording = None;
+        scene.playback = Some((replay, 0));
         Ok(scene)
     }
 
     pub fn with_pack(ctx: &mut tetra::Context, pack: LevelPack) -> tetra::Result<GameScene> {
-        let first_level = &pack.levels[0];
+        GameScene::with_pack_at(ctx, pack, 0)
+    }
+
+    pub fn with_pack_at(
+        ctx: &mut tetra::Context,
+        pack: LevelPack,
+        start_level: usize,
+    ) -> tetra::Result<GameScene> {
+        let first_level = &pack.levels[start_level];
         let palette = first_level.palette;
         let label = LevelLabel::new(ctx, &first_level.name, &first_level.author);
-        Ok(GameScene {
+        let recording = Replay::new(first_level).ok();
+        // Packs loaded from disk always know their level files, single levels don't
+        let pack_id = (pack.level_files.len() == pack.levels.len()).then(|| pack.id.clone());
+        let best_runs = match &pack_id {
+            Some(id) => pack
+                .levels
+                .iter()
+                .zip(&pack.level_files)
+                .map(|(level, file)| {
+                    Replay::load_file(SaveData::ghost_path(id, file))
+                        .ok()
+                        .filter(|replay| replay.check_level(level).is_ok())
+                })
+                .collect(),
+            None => vec![None; pack.levels.len()],
+        };
+        let mut save = SaveData::load();
+        let run = match &pack_id {
+            Some(id) if start_level == 0 => {
+                save.pack_mut(id).attempts += 1;
+                Some(Splits::default())
+            }
+            _ => None,
+        };
+        let personal_best = pack_id
+            .as_ref()
+            .and_then(|id| save.pack(id)?.personal_best.clone())
+            .filter(|pb| pb.is_complete_run_of(&pack));
+        let mut scene = GameScene {
             world: World::new(first_level.clone()),
             camera: Camera::new(Self::INNER_SIZE.x as f32, Self::INNER_SIZE.y as f32),
             scaler: ScreenScaler::with_window_size(
@@ -200,11 +395,175 @@ impl GameScene {
                 ScalingMode::ShowAll,
             )?,
             palette_system: PaletteSystem::new(palette),
-            level_pack: pack,
-            current_level: 0,
+            current_level: start_level,
             playtest: false,
             label,
-        })
+            accumulator: 0.,
+            pending_input: InputFrame::default(),
+            recording,
+            playback: None,
+            best_runs,
+            ghost: None,
+            save,
+            pack_id,
+            saved_deaths: 0,
+            run,
+            personal_best,
+            segment_ticks: 0,
+            last_delta: None,
+            timer: TimerLabel::default(),
+            medal_label: MedalLabel::default(),
+            level_pack: pack,
+        };
+        scene.start_ghost();
+        scene.save_progress(None);
+        Ok(scene)
+    }
+
+    fn save_recording(&self) {
+        let Some(recording) = &self.recording else {
+            return;
+        };
+        let level = &self.level_pack.levels[self.current_level];
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let path = Path::new(Self::REPLAYS_DIRECTORY).join(format!(
+            "{}-{}.{}",
+            util::sanitize_file_name(&level.name),
+            timestamp,
+            Replay::EXTENSION
+        ));
+        let res = fs::create_dir_all(Self::REPLAYS_DIRECTORY)
+            .map_err(ReplayError::Io)
+            .and_then(|_| recording.save_file(&path));
+        match res {
+            Ok(_) => println!("Saved replay at {}", path.display()),
+            Err(e) => println!("Error saving replay at {}: {:?}", path.display(), e),
+        }
+    }
+
+    /// Keeps the run that just finished if it beats the best one for the current level.
+    fn record_best_run(&mut self) {
+        let Some(recording) = self.recording.take() else {
+            return;
+        };
+        match &mut self.best_runs[self.current_level] {
+            Some(best) if best.inputs.len() <= recording.inputs.len() => {}
+            best => {
+                if let Some(pack_id) = &self.pack_id {
+                    let file = &self.level_pack.level_files[self.current_level];
+                    let path = SaveData::ghost_path(pack_id, file);
+                    let res = path
+                        .parent()
+                        .map_or(Ok(()), fs::create_dir_all)
+                        .map_err(ReplayError::Io)
+                        .and_then(|_| recording.save_file(&path));
+                    if let Err(e) = res {
+                        println!("Error saving ghost at {}: {:?}", path.display(), e);
+                    }
+                }
+                *best = Some(recording);
+            }
+        }
+    }
+
+    /// Writes the deaths since the last save and the current level to the save file, along with
+    /// the completion time if the level was just finished.
+    fn save_progress(&mut self, completed_in: Option<u64>) {
+        let Some(pack_id) = &self.pack_id else {
+            return;
+        };
+        let file = &self.level_pack.level_files[self.current_level];
+        let pack = self.save.pack_mut(pack_id);
+        pack.last_played = Some(file.clone());
+        let level = pack.level_mut(file);
+        level.deaths += (self.world.deaths() - self.saved_deaths) as u64;
+        self.saved_deaths = self.world.deaths();
+        if let Some(ticks) = completed_in {
+            level.complete(ticks);
+        }
+        if let Err(e) = self.save.save() {
+            println!("Error saving progress: {:?}", e);
+        }
+    }
+
+    fn start_ghost(&mut self) {
+        let level = &self.level_pack.levels[self.current_level];
+        self.ghost = self.best_runs[self.current_level]
+            .as_ref()
+            .map(|best| Ghost::new(level, best.inputs.clone()));
+    }
+
+    /// Ends the segment of the current level and compares the run with the personal best.
+    fn split(&mut self) {
+        let ticks = std::mem::take(&mut self.segment_ticks);
+        let Some(run) = &mut self.run else {
+            return;
+        };
+        run.push(&self.level_pack.level_files[self.current_level], ticks);
+        let index = run.segments.len() - 1;
+        self.last_delta = self
+            .personal_best
+            .as_ref()
+            .and_then(|pb| pb.split(index))
+            .map(|pb| run.total() as i64 - pb as i64);
+    }
+
+    /// Keeps the run through the pack if it is a new personal best and exports the splits.
+    fn finish_run(&mut self) {
+        let (Some(run), Some(pack_id)) = (self.run.take(), &self.pack_id) else {
+            return;
+        };
+        let progress = self.save.pack_mut(pack_id);
+        let is_personal_best = match &self.personal_best {
+            Some(pb) => run.total() < pb.total(),
+            None => true,
+        };
+        if is_personal_best {
+            progress.personal_best = Some(run);
+        }
+        if let Err(e) = self.save.save() {
+            println!("Error saving progress: {:?}", e);
+        }
+        match Splits::save_lss(&self.level_pack, self.save.pack_mut(pack_id)) {
+            Ok(path) => println!("Saved splits at {}", path.display()),
+            Err(e) => println!("Error saving splits: {:?}", e),
+        }
+    }
+
+    /// Input for the next tick, either from the player or from the replay being watched.
+    fn next_input(&mut self) -> Option<InputFrame> {
+        match &mut self.playback {
+            Some((replay, cursor)) => {
+                let input = replay.inputs.get(*cursor).copied();
+                *cursor += 1;
+                input
+            }
+            None => Some(self.pending_input),
+        }
+    }
+
+    fn update_camera(&mut self) {
+        self.camera.position = self
+            .world
+            .interpolated_player_pos(self.accumulator / World::TICK);
+        let world_rect = self.world.get_world_rect();
+        let cam_rect = self.camera.visible_rect();
+        if cam_rect.left() < world_rect.left() {
+            self.camera.position.x += world_rect.left() - cam_rect.left();
+        }
+        if cam_rect.right() > world_rect.right() {
+            self.camera.position.x += world_rect.right() - cam_rect.right();
+        }
+        if cam_rect.top() < world_rect.top() {
+            self.camera.position.y += world_rect.top() - cam_rect.top();
+        }
+        if cam_rect.bottom() > world_rect.bottom() {
+            self.camera.position.y += world_rect.bottom() - cam_rect.bottom();
+        }
+        self.camera.update();
     }
 }
 
@@ -223,51 +582,93 @@ impl Scene for GameScene {
         _egui_ctx: &CtxRef,
     ) -> tetra::Result<Transition> {
         if input::is_key_pressed(ctx, Key::Escape) {
+            self.save_progress(None);
             return Ok(Transition::Pop);
         }
 
         let dt = tetra::time::get_delta_time(ctx).as_secs_f32();
 
-        self.world.update(ctx);
+        self.pending_input.merge(&InputFrame::from_context(ctx));
+        self.accumulator += dt;
+        let mut ticks = 0;
+        if input::is_key_pressed(ctx, Key::F2) {
+            self.save_recording();
+        }
+        while self.accumulator >= World::TICK && !self.world.win() {
+            let Some(input) = self.next_input() else {
+                // The replay is over
+                return Ok(Transition::Pop);
+            };
+            self.world.step(&input, World::TICK);
+            // The run keeps its time through resets, the level attempt starts over
+            self.segment_ticks += 1;
+            if input.reset {
+                if self.recording.is_some() {
+                    let level = &self.level_pack.levels[self.current_level];
+                    self.recording = Replay::new(level).ok();
+                }
+                self.start_ghost();
+            } else {
+                if let Some(recording) = &mut self.recording {
+                    recording.record(&input);
+                }
+                if let Some(ghost) = &mut self.ghost {
+                    ghost.step();
+                }
+            }
+            self.pending_input.consume_presses();
+            self.accumulator -= World::TICK;
+            ticks += 1;
+            if ticks == Self::MAX_TICKS_PER_FRAME {
+                // Drop the time we can't catch up on instead of slowing down further
+                self.accumulator %= World::TICK;
+                break;
+            }
+        }
         self.label.update_timer(dt);
+        self.medal_label.update_timer(dt);
         if self.world.win() {
+            if self.playback.is_some() {
+                return Ok(Transition::Pop);
+            }
+            let ticks = self.recording.as_ref().map(|r| r.inputs.len() as u64);
+            self.save_progress(ticks);
+            if let Some(ticks) = ticks {
+                let medals = &self.level_pack.levels[self.current_level].medals;
+                self.medal_label.set(medals, ticks);
+            }
+            self.split();
+            self.record_best_run();
             match self.playtest {
                 true => {
                     self.world.reset();
+                    let level = &self.level_pack.levels[self.current_level];
+                    self.recording = Replay::new(level).ok();
+                    self.start_ghost();
                 }
                 false => {
                     self.current_level += 1;
                     if self.current_level == self.level_pack.levels.len() {
+                        self.finish_run();
                         return Ok(Transition::Pop);
                     }
                     let next_level = self.level_pack.levels[self.current_level].clone();
                     self.palette_system.change_palette(next_level.palette);
                     self.label.set(&next_level.name, &next_level.author);
+                    self.recording = Replay::new(&next_level).ok();
                     self.world = World::new(next_level);
+                    self.saved_deaths = 0;
+                    self.start_ghost();
+                    self.save_progress(None);
                 }
             }
         }
-        self.camera.position = self.world.player_pos();
-        let world_rect = self.world.get_world_rect();
-        let cam_rect = self.camera.visible_rect();
-        if cam_rect.left() < world_rect.left() {
-            self.camera.position.x += world_rect.left() - cam_rect.left();
-        }
-        if cam_rect.right() > world_rect.right() {
-            self.camera.position.x += world_rect.right() - cam_rect.right();
-        }
-        if cam_rect.top() < world_rect.top() {
-            self.camera.position.y += world_rect.top() - cam_rect.top();
-        }
-        if cam_rect.bottom() > world_rect.bottom() {
-            self.camera.position.y += world_rect.bottom() - cam_rect.bottom();
-        }
-        self.camera.update();
         self.palette_system.update(dt);
         Ok(Transition::None)
     }
 
     fn draw(&mut self, ctx: &mut tetra::Context, assets: &Assets) -> tetra::Result {
+        self.update_camera();
         assets
             .shader
             .set_uniform(ctx, "u_color_a", self.palette_system.dark());
@@ -292,7 +693,11 @@ impl Scene for GameScene {
         graphics::set_canvas(ctx, self.scaler.canvas());
         graphics::set_transform_matrix(ctx, self.camera.as_matrix());
         graphics::clear(ctx, Color::BLACK);
-        self.world.draw(ctx, assets);
+        let alpha = self.accumulator / World::TICK;
+        self.world.draw(ctx, assets, alpha);
+        if let Some(ghost) = &self.ghost {
+            ghost.draw(ctx, assets, alpha);
+        }
         graphics::reset_transform_matrix(ctx);
         graphics::reset_canvas(ctx);
         graphics::clear(ctx, Color::BLACK);
@@ -307,6 +712,22 @@ impl Scene for GameScene {
                 self.palette_system.light(),
             );
         }
+        self.medal_label.draw(
+            ctx,
+            assets,
+            self.palette_system.dark(),
+            self.palette_system.light(),
+        );
+        if let Some(run) = &self.run {
+            self.timer.draw(
+                ctx,
+                assets,
+                run.total() + self.segment_ticks,
+                self.last_delta,
+                self.palette_system.dark(),
+                self.palette_system.light(),
+            );
+        }
         Ok(())
     }
 }
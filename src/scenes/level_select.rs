@@ -0,0 +1,154 @@
+use egui_tetra::egui;
+use tetra::{
+    graphics::{self, Color},
+    input::{self, Key},
+};
+
+use crate::{
+    level::LevelPack,
+    save::{PackProgress, SaveData},
+    util,
+    world::World,
+};
+
+use super::{GameScene, Scene, Transition};
+
+pub struct LevelSelectScene {
+    pack: LevelPack,
+    save: SaveData,
+    selected: Option<usize>,
+    back: bool,
+    /// Set when a level is started, so progress made in it shows up once we're back.
+    reload_save: bool,
+}
+
+impl LevelSelectScene {
+    pub fn new(pack: LevelPack) -> Self {
+        Self {
+            pack,
+            save: SaveData::load(),
+            selected: None,
+            back: false,
+            reload_save: false,
+        }
+    }
+}
+
+impl Scene for LevelSelectScene {
+    fn update(
+        &mut self,
+        ctx: &mut tetra::Context,
+        _egui_ctx: &egui::CtxRef,
+    ) -> tetra::Result<Transition> {
+        if self.reload_save {
+            self.reload_save = false;
+            self.save = SaveData::load();
+        }
+        if self.back || input::is_key_pressed(ctx, Key::Escape) {
+            return Ok(Transition::Pop);
+        }
+        if let Some(level) = self.selected.take() {
+            self.reload_save = true;
+            return Ok(Transition::Push(Box::new(GameScene::with_pack_at(
+                ctx,
+                self.pack.clone(),
+                level,
+            )?)));
+        }
+        Ok(Transition::None)
+    }
+
+    fn egui_layout(
+        &mut self,
+        _ctx: &mut tetra::Context,
+        egui_ctx: &egui::CtxRef,
+    ) -> Result<(), egui_tetra::Error> {
+        let default_progress = PackProgress::default();
+        let progress = self.save.pack(&self.pack.id).unwrap_or(&default_progress);
+        egui::Window::new(&self.pack.name).show(egui_ctx, |ui| {
+            ui.label(format!(
+                "{}/{} levels completed",
+                progress.completed_count(&self.pack),
+                self.pack.levels.len()
+            ));
+            ui.separator();
+            egui::Grid::new("levels").striped(true).show(ui, |ui| {
+                for header in [
+                    "#",
+                    "Level",
+                    "Author",
+                    "Status",
+                    "Best time",
+                    "Medal",
+                    "Deaths",
+                    "Problems",
+                    "",
+                ] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (i, (level, file)) in self
+                    .pack
+                    .levels
+                    .iter()
+                    .zip(&self.pack.level_files)
+                    .enumerate()
+                {
+                    let unlocked = progress.is_unlocked(&self.pack, i);
+                    let level_progress = progress.level(file).cloned().unwrap_or_default();
+                    ui.label((i + 1).to_string());
+                    ui.label(&level.name);
+                    ui.label(&level.author);
+                    ui.label(match (unlocked, level_progress.completed) {
+                        (false, _) => "Locked",
+                        (true, true) => "Completed",
+                        (true, false) => "",
+                    });
+                    ui.label(match level_progress.best_ticks {
+                        Some(ticks) => util::format_time(World::ticks_to_secs(ticks)),
+                        None => "-".into(),
+                    });
+                    let medal = level_progress
+                        .best_ticks
+                        .and_then(|ticks| level.medals.medal(ticks));
+                    ui.label(match medal {
+                        Some(medal) => medal.to_string(),
+                        None if level.medals.is_empty() => "".into(),
+                        None => "-".into(),
+                    });
+                    ui.label(level_progress.deaths.to_string());
+                    let diagnostics: Vec<_> = self.pack.level_diagnostics(file).collect();
+                    if diagnostics.is_empty() {
+                        ui.label("");
+                    } else {
+                        let kind = if diagnostics.iter().any(|d| d.is_error()) {
+                            "Errors"
+                        } else {
+                            "Warnings"
+                        };
+                        let details: Vec<_> = diagnostics.iter().map(ToString::to_string).collect();
+                        ui.label(format!("{} ({})", kind, diagnostics.len()))
+                            .on_hover_text(details.join("\n"));
+                    }
+                    if ui
+                        .add_enabled(unlocked, egui::Button::new("Play"))
+                        .clicked()
+                    {
+                        self.selected = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            if ui.button("Back").clicked() {
+                self.back = true;
+            }
+        });
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut tetra::Context, _assets: &crate::Assets) -> tetra::Result {
+        graphics::clear(ctx, Color::BLACK);
+        Ok(())
+    }
+}
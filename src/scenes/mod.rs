@@ -26,8 +26,10 @@ pub enum Transition {
 
 mod editor;
 mod game;
+mod level_select;
 mod start;
 
 pub use editor::EditorScene;
 pub use game::GameScene;
+pub use level_select::LevelSelectScene;
 pub use start::StartScene;
@@ -1,18 +1,21 @@
 use egui_tetra::egui;
 use tetra::graphics::{self, Color};
 
-use crate::level::LevelPack;
+use crate::{level::LevelPack, save::SaveData};
 
-use super::{EditorScene, GameScene, Scene, Transition};
+use super::{EditorScene, GameScene, LevelSelectScene, Scene, Transition};
 
 pub struct StartScene {
     editor: bool,
     quit: bool,
     play: bool,
     play_pack: bool,
+    select_level: bool,
     manage_packs: bool,
     packs: Vec<LevelPack>,
     selected_pack: usize,
+    start_level: usize,
+    save: SaveData,
 }
 
 impl StartScene {
@@ -22,9 +25,12 @@ impl StartScene {
             quit: false,
             play: false,
             play_pack: false,
+            select_level: false,
             manage_packs: false,
             packs: Vec::new(),
             selected_pack: 0,
+            start_level: 0,
+            save: SaveData::default(),
         }
     }
 
@@ -32,6 +38,7 @@ impl StartScene {
         if let Ok(packs) = LevelPack::get_packs_in_directory("levels") {
             self.packs = packs;
         }
+        self.save = SaveData::load();
     }
 }
 
@@ -47,7 +54,19 @@ impl Scene for StartScene {
             let pack = self.packs.remove(self.selected_pack);
             self.packs.clear();
             self.selected_pack = 0;
-            return Ok(Transition::Push(Box::new(GameScene::with_pack(ctx, pack)?)));
+            return Ok(Transition::Push(Box::new(GameScene::with_pack_at(
+                ctx,
+                pack,
+                self.start_level,
+            )?)));
+        }
+        if self.select_level {
+            self.select_level = false;
+            self.play = false;
+            let pack = self.packs.remove(self.selected_pack);
+            self.packs.clear();
+            self.selected_pack = 0;
+            return Ok(Transition::Push(Box::new(LevelSelectScene::new(pack))));
         }
         if self.editor {
             self.editor = false;
@@ -101,10 +120,48 @@ impl Scene for StartScene {
                             )
                         },
                     );
+                    let pack = &self.packs[self.selected_pack];
+                    if !pack.author.is_empty() {
+                        ui.label(format!("Created by {}", pack.author));
+                    }
+                    if !pack.version.is_empty() {
+                        ui.label(format!("Version {}", pack.version));
+                    }
+                    if !pack.description.is_empty() {
+                        ui.label(&pack.description);
+                    }
+                    let progress = self.save.pack(&pack.id);
+                    let completed = progress.map_or(0, |p| p.completed_count(pack));
+                    ui.label(format!(
+                        "{} levels, {} completed",
+                        pack.levels.len(),
+                        completed
+                    ));
+                    if !pack.diagnostics.is_empty() {
+                        ui.collapsing(format!("Problems ({})", pack.diagnostics.len()), |ui| {
+                            for (file, diagnostic) in &pack.diagnostics {
+                                ui.label(format!("{}: {}", file, diagnostic));
+                            }
+                        });
+                    }
+                    let continue_index = progress.and_then(|p| p.continue_index(pack));
+                    if let Some(index) = continue_index {
+                        if ui
+                            .button(format!("Continue ({})", pack.levels[index].name))
+                            .clicked()
+                        {
+                            self.start_level = index;
+                            self.play_pack = true;
+                        }
+                    }
                 }
                 if ui.button("Play pack").clicked() {
+                    self.start_level = 0;
                     self.play_pack = true;
                 }
+                if !self.packs.is_empty() && ui.button("Select level").clicked() {
+                    self.select_level = true;
+                }
             });
         egui::Window::new("Manage Packs")
             .open(&mut self.manage_packs)
@@ -0,0 +1,288 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+use crate::{input::InputFrame, level::Level, world::World};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    None,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Jump {
+    Release,
+    Press,
+    Hold,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Action {
+    direction: Direction,
+    jump: Jump,
+}
+
+impl Action {
+    const ALL: [Action; 9] = {
+        use Direction as D;
+        use Jump as J;
+        [
+            Action::new(D::None, J::Release),
+            Action::new(D::Left, J::Release),
+            Action::new(D::Right, J::Release),
+            Action::new(D::None, J::Press),
+            Action::new(D::Left, J::Press),
+            Action::new(D::Right, J::Press),
+            Action::new(D::None, J::Hold),
+            Action::new(D::Left, J::Hold),
+            Action::new(D::Right, J::Hold),
+        ]
+    };
+
+    const fn new(direction: Direction, jump: Jump) -> Action {
+        Action { direction, jump }
+    }
+
+    fn frame(&self, first: bool) -> InputFrame {
+        InputFrame {
+            left: self.direction == Direction::Left,
+            right: self.direction == Direction::Right,
+            jump: first && self.jump == Jump::Press,
+            jump_held: self.jump != Jump::Release,
+            reset: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Solution {
+    /// The door was reached with every key. Contains the inputs to feed `World::step`, one per
+    /// tick.
+    Solved(Vec<InputFrame>),
+    /// Every state reachable with the solver's coarse inputs was explored without reaching the
+    /// door. This is not a proof: finer inputs may still complete the level.
+    NotFound,
+    /// The search gave up before exploring every reachable state.
+    Inconclusive,
+}
+
+/// Breadth-first search over the inputs of a level, using the game's own simulation.
+///
+/// Every input is held for a few frames and states closer than a pixel are merged, so a
+/// level needing frame-perfect inputs may not be solved.
+pub struct Solver {
+    max_states: usize,
+    frames_per_action: usize,
+}
+
+impl Solver {
+    pub const DEFAULT_MAX_STATES: usize = 200_000;
+    pub const DEFAULT_FRAMES_PER_ACTION: usize = 4;
+
+    pub fn new() -> Solver {
+        Solver {
+            max_states: Self::DEFAULT_MAX_STATES,
+            frames_per_action: Self::DEFAULT_FRAMES_PER_ACTION,
+        }
+    }
+
+    /// Starts searching a level, leaving it to the caller to run the search.
+    pub fn start(&self, level: &Level) -> Search {
+        let world = World::new(level.clone());
+        let mut visited = HashSet::new();
+        visited.insert(state_key(&world));
+        Search {
+            max_states: self.max_states,
+            frames_per_action: self.frames_per_action,
+            visited,
+            // The root node has no action, its entry is never read
+            nodes: vec![(0, Action::new(Direction::None, Jump::Release))],
+            queue: VecDeque::from([(world, 0)]),
+        }
+    }
+
+    /// Searches a level until the search is over, which can take a while on big levels.
+    #[cfg(test)]
+    pub fn solve(&self, level: &Level) -> Solution {
+        let mut search = self.start(level);
+        loop {
+            if let Some(solution) = search.run(usize::MAX) {
+                return solution;
+            }
+        }
+    }
+}
+
+/// Hash of the world's quantized state. Two states colliding are merged, which is rare enough
+/// next to the merging the quantization already does.
+fn state_key(world: &World) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    world.quantized_state().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Search in progress, which can be run a few states at a time to keep frames short.
+pub struct Search {
+    max_states: usize,
+    frames_per_action: usize,
+    visited: HashSet<u64>,
+    nodes: Vec<(usize, Action)>,
+    queue: VecDeque<(World, usize)>,
+}
+
+impl Search {
+    /// Number of distinct states found so far.
+    pub fn explored(&self) -> usize {
+        self.visited.len()
+    }
+
+    fn trace(&self, mut node: usize) -> Vec<InputFrame> {
+        let mut actions = vec![];
+        while node != 0 {
+            let (parent, action) = self.nodes[node];
+            actions.push(action);
+            node = parent;
+        }
+        actions
+            .into_iter()
+            .rev()
+            .flat_map(|action| {
+                (0..self.frames_per_action).map(move |frame| action.frame(frame == 0))
+            })
+            .collect()
+    }
+
+    /// Expands up to `budget` states, returning the result once the search is over.
+    pub fn run(&mut self, budget: usize) -> Option<Solution> {
+        for _ in 0..budget {
+            let Some((world, node)) = self.queue.pop_front() else {
+                return Some(Solution::NotFound);
+            };
+            for action in Action::ALL {
+                let mut next = world.clone();
+                let mut dead = false;
+                for frame in 0..self.frames_per_action {
+                    next.step(&action.frame(frame == 0), World::TICK);
+                    if next.deaths() > world.deaths() {
+                        dead = true;
+                        break;
+                    }
+                    if next.win() {
+                        break;
+                    }
+                }
+                if dead {
+                    continue;
+                }
+                if next.win() {
+                    self.nodes.push((node, action));
+                    return Some(Solution::Solved(self.trace(self.nodes.len() - 1)));
+                }
+                if !self.visited.insert(state_key(&next)) {
+                    continue;
+                }
+                if self.visited.len() > self.max_states {
+                    return Some(Solution::Inconclusive);
+                }
+                self.nodes.push((node, action));
+                self.queue.push_back((next, self.nodes.len() - 1));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Solution, Solver};
+    use crate::{level::Level, world::World};
+
+    #[test]
+    fn solves_level_with_key() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 3
+            door: 8 3
+            dark:
+            ##########
+            #........#
+            #....k...#
+            #...###..#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let Solution::Solved(inputs) = Solver::new().solve(&level) else {
+            panic!("level should be solvable");
+        };
+        let mut world = World::new(level);
+        for input in &inputs {
+            world.step(input, World::TICK);
+        }
+        assert!(world.win());
+    }
+
+    #[test]
+    fn search_runs_in_steps() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 2
+            door: 8 2
+            dark:
+            ##########
+            #........#
+            #........#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut search = Solver::new().start(&level);
+        let mut runs = 1;
+        let solution = loop {
+            if let Some(solution) = search.run(1) {
+                break solution;
+            }
+            runs += 1;
+        };
+        assert!(runs > 1);
+        assert_eq!(solution, Solver::new().solve(&level));
+    }
+
+    #[test]
+    fn walled_off_key_is_unsolvable() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 3
+            door: 8 3
+            dark:
+            ##########
+            #....###.#
+            #....#k#.#
+            #....###.#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        assert_eq!(Solver::new().solve(&level), Solution::NotFound);
+    }
+}
@@ -0,0 +1,192 @@
+use std::{fmt::Write, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    level::LevelPack,
+    save::{PackProgress, SaveError},
+    util,
+    world::World,
+};
+
+/// Time spent on each level of a run through a pack, in world ticks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Splits {
+    /// Level files, so splits from a pack that changed since can be told apart.
+    pub levels: Vec<String>,
+    pub segments: Vec<u64>,
+}
+
+impl Splits {
+    pub const DIRECTORY: &'static str = "splits";
+    pub const EXTENSION: &'static str = "lss";
+
+    pub fn push(&mut self, level_file: &str, ticks: u64) {
+        self.levels.push(level_file.into());
+        self.segments.push(ticks);
+    }
+
+    pub fn total(&self) -> u64 {
+        self.segments.iter().sum()
+    }
+
+    /// Time from the start of the run to the end of the level at `index`.
+    pub fn split(&self, index: usize) -> Option<u64> {
+        (index < self.segments.len()).then(|| self.segments[..=index].iter().sum())
+    }
+
+    /// Whether these splits are a full run of the pack as it is now.
+    pub fn is_complete_run_of(&self, pack: &LevelPack) -> bool {
+        self.levels == pack.level_files
+    }
+
+    pub fn path(pack: &LevelPack) -> PathBuf {
+        PathBuf::from(Self::DIRECTORY).join(format!(
+            "{}.{}",
+            util::sanitize_file_name(&pack.id),
+            Self::EXTENSION
+        ))
+    }
+
+    /// Writes the personal best of a pack in LiveSplit's splits format. Best segments are the
+    /// best times of each level, even outside of full runs.
+    pub fn to_lss(pack: &LevelPack, progress: &PackProgress) -> String {
+        let personal_best = progress
+            .personal_best
+            .as_ref()
+            .filter(|pb| pb.is_complete_run_of(pack));
+        let mut lss = String::new();
+        let _ = writeln!(lss, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(lss, r#"<Run version="1.7.0">"#);
+        let _ = writeln!(lss, "  <GameIcon />");
+        let _ = writeln!(lss, "  <GameName>Upfall</GameName>");
+        let _ = writeln!(
+            lss,
+            "  <CategoryName>{}</CategoryName>",
+            xml_escape(&pack.name)
+        );
+        let _ = writeln!(lss, "  <Offset>00:00:00</Offset>");
+        let _ = writeln!(lss, "  <AttemptCount>{}</AttemptCount>", progress.attempts);
+        let _ = writeln!(lss, "  <AttemptHistory />");
+        let _ = writeln!(lss, "  <Segments>");
+        for (i, (level, file)) in pack.levels.iter().zip(&pack.level_files).enumerate() {
+            let _ = writeln!(lss, "    <Segment>");
+            let _ = writeln!(lss, "      <Name>{}</Name>", xml_escape(&level.name));
+            let _ = writeln!(lss, "      <Icon />");
+            let _ = writeln!(lss, "      <SplitTimes>");
+            match personal_best.and_then(|pb| pb.split(i)) {
+                Some(split) => {
+                    let _ = writeln!(lss, r#"        <SplitTime name="Personal Best">"#);
+                    let _ = writeln!(lss, "          {}", lss_time(split));
+                    let _ = writeln!(lss, "        </SplitTime>");
+                }
+                None => {
+                    let _ = writeln!(lss, r#"        <SplitTime name="Personal Best" />"#);
+                }
+            }
+            let _ = writeln!(lss, "      </SplitTimes>");
+            match progress.level(file).and_then(|level| level.best_ticks) {
+                Some(best) => {
+                    let _ = writeln!(
+                        lss,
+                        "      <BestSegmentTime>{}</BestSegmentTime>",
+                        lss_time(best)
+                    );
+                }
+                None => {
+                    let _ = writeln!(lss, "      <BestSegmentTime />");
+                }
+            }
+            let _ = writeln!(lss, "      <SegmentHistory />");
+            let _ = writeln!(lss, "    </Segment>");
+        }
+        let _ = writeln!(lss, "  </Segments>");
+        let _ = writeln!(lss, "  <AutoSplitterSettings />");
+        let _ = writeln!(lss, "</Run>");
+        lss
+    }
+
+    pub fn save_lss(pack: &LevelPack, progress: &PackProgress) -> Result<PathBuf, SaveError> {
+        let path = Self::path(pack);
+        fs::create_dir_all(Self::DIRECTORY).map_err(SaveError::Io)?;
+        fs::write(&path, Self::to_lss(pack, progress)).map_err(SaveError::Io)?;
+        Ok(path)
+    }
+}
+
+/// Formats the difference to a personal best, like `+0:01.250`.
+pub fn format_delta(ticks: i64) -> String {
+    let sign = if ticks < 0 { '-' } else { '+' };
+    let secs = World::ticks_to_secs(ticks.unsigned_abs());
+    format!("{}{}", sign, util::format_time(secs))
+}
+
+/// Game and real time of a split. The timer follows the simulation, so both are the same.
+fn lss_time(ticks: u64) -> String {
+    let hundred_ns = (World::ticks_to_secs(ticks) * 10_000_000.).round() as u64;
+    let secs = hundred_ns / 10_000_000;
+    let time = format!(
+        "{:02}:{:02}:{:02}.{:07}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        hundred_ns % 10_000_000
+    );
+    format!("<RealTime>{}</RealTime><GameTime>{}</GameTime>", time, time)
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_delta, lss_time, Splits};
+    use crate::{
+        level::{Level, LevelPack},
+        save::PackProgress,
+    };
+
+    #[test]
+    fn splits_and_lss() {
+        let pack = LevelPack {
+            name: "Pack & Co".into(),
+            levels: vec![Level::default(), Level::default()],
+            level_files: vec!["a".into(), "b".into()],
+            ..Default::default()
+        };
+        let mut run = Splits::default();
+        run.push("a", 90);
+        run.push("b", 60);
+        assert_eq!(run.split(0), Some(90));
+        assert_eq!(run.split(1), Some(150));
+        assert_eq!(run.split(2), None);
+        assert!(run.is_complete_run_of(&pack));
+
+        let mut progress = PackProgress::default();
+        progress.level_mut("a").complete(80);
+        progress.personal_best = Some(run);
+        let lss = Splits::to_lss(&pack, &progress);
+        assert!(lss.contains("<CategoryName>Pack &amp; Co</CategoryName>"));
+        assert!(lss.contains(&lss_time(150)));
+        assert!(lss.contains(&format!(
+            "<BestSegmentTime>{}</BestSegmentTime>",
+            lss_time(80)
+        )));
+        assert!(lss.contains("<BestSegmentTime />"));
+    }
+
+    #[test]
+    fn time_formats() {
+        assert_eq!(
+            lss_time(90),
+            "<RealTime>00:00:01.5000000</RealTime><GameTime>00:00:01.5000000</GameTime>"
+        );
+        assert_eq!(format_delta(-30), "-0:00.500");
+        assert_eq!(format_delta(3660), "+1:01.000");
+    }
+}
@@ -24,10 +24,39 @@ pub enum Tile {
     Spike(Facing),
     Portal(Axis),
     Key,
-    Spring(Facing),
+    /// Launches the player away from the side it faces, with a force in pixels per tick.
+    Spring(Facing, u8),
+    Checkpoint,
+    ColorKey(KeyColor),
+    Lock(KeyColor),
+    /// Platform that is only solid from the side it faces.
+    OneWay(Facing),
+    /// Solid block that breaks a short time after being stood on.
+    Crumbling,
+    /// Solid block carrying the player standing on it towards `Left` or `Right`, with a speed
+    /// in tenths of a pixel per tick.
+    Conveyor(Facing, u8),
+    /// Toggles the switch blocks of its group when touched, in both worlds if set.
+    Switch(u8, bool),
+    /// Block that is solid or passable depending on the state of its group, solid at first if
+    /// set.
+    SwitchBlock(u8, bool),
+    /// Sends the player to the other teleporter with the same pair id, in either world.
+    Teleporter(u8),
+    /// Changes which way the player falls, without switching worlds.
+    Gravity(GravityZone),
+    /// Solid block the player slides on.
+    Ice,
 }
 
 impl Tile {
+    pub const DEFAULT_SPRING_FORCE: u8 = 10;
+    pub const MAX_SPRING_FORCE: u8 = 15;
+    pub const DEFAULT_CONVEYOR_SPEED: u8 = 20;
+    pub const MAX_CONVEYOR_SPEED: u8 = 40;
+    pub const MAX_SWITCH_GROUP: u8 = 9;
+    pub const MAX_TELEPORTER_PAIR: u8 = 9;
+
     pub fn type_str(&self) -> &str {
         match self {
             Tile::None => "None",
@@ -35,9 +64,33 @@ impl Tile {
             Tile::Spike(_) => "Spike",
             Tile::Portal(_) => "Portal",
             Tile::Key => "Key",
-            Tile::Spring(_) => "Spring",
+            Tile::Spring(..) => "Spring",
+            Tile::Checkpoint => "Checkpoint",
+            Tile::ColorKey(_) => "Colored Key",
+            Tile::Lock(_) => "Lock",
+            Tile::OneWay(_) => "One-Way",
+            Tile::Crumbling => "Crumbling",
+            Tile::Conveyor(..) => "Conveyor",
+            Tile::Switch(..) => "Switch",
+            Tile::SwitchBlock(..) => "Switch Block",
+            Tile::Teleporter(_) => "Teleporter",
+            Tile::Gravity(_) => "Gravity Zone",
+            Tile::Ice => "Ice",
         }
     }
+
+    /// Whether the tile blocks the player from every side when the level starts.
+    pub fn is_solid(&self) -> bool {
+        matches!(
+            self,
+            Tile::Solid
+                | Tile::Lock(_)
+                | Tile::Crumbling
+                | Tile::Conveyor(..)
+                | Tile::Ice
+                | Tile::SwitchBlock(_, true)
+        )
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display)]
@@ -49,6 +102,39 @@ pub enum Facing {
     Right,
 }
 
+/// Color of a key and of the lock blocks it opens. Levels are drawn with only two colors, so
+/// colors are told apart in game by the number of dots on keys and locks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Display)]
+pub enum KeyColor {
+    #[default]
+    Red,
+    Green,
+    Blue,
+    Yellow,
+}
+
+impl KeyColor {
+    pub const ALL: [KeyColor; 4] = [
+        KeyColor::Red,
+        KeyColor::Green,
+        KeyColor::Blue,
+        KeyColor::Yellow,
+    ];
+
+    fn dots(&self) -> usize {
+        *self as usize + 1
+    }
+}
+
+/// What a gravity zone does to the player entering it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display)]
+pub enum GravityZone {
+    #[default]
+    Flip,
+    Down,
+    Up,
+}
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display)]
 pub enum Axis {
     #[default]
@@ -64,8 +150,18 @@ impl Tile {
         const SPIKE_LENGTH: f32 = 14. / 16.;
         match *self {
             Tile::None => Rectangle::default(),
-            Tile::Solid | Tile::Portal(_) => Rectangle::new(pos.x, pos.y, size.x, size.y),
-            Tile::Spike(dir) | Tile::Spring(dir) => match dir {
+            Tile::Solid
+            | Tile::Portal(_)
+            | Tile::Checkpoint
+            | Tile::Lock(_)
+            | Tile::Crumbling
+            | Tile::Conveyor(..)
+            | Tile::Switch(..)
+            | Tile::SwitchBlock(..)
+            | Tile::Teleporter(_)
+            | Tile::Gravity(_)
+            | Tile::Ice => Rectangle::new(pos.x, pos.y, size.x, size.y),
+            Tile::Spike(dir) | Tile::Spring(dir, _) => match dir {
                 Facing::Up => Rectangle::new(
                     pos.x + size.x * SPIKE_SIDE_GAP,
                     pos.y + size.y * SPIKE_FRONT_GAP,
@@ -91,14 +187,42 @@ impl Tile {
                     size.y * SPIKE_LENGTH,
                 ),
             },
-            Tile::Key => Rectangle::new(pos.x + 4., pos.y + 4., size.x - 8., size.y - 8.),
+            Tile::OneWay(dir) => {
+                const THICKNESS: f32 = 3. / 16.;
+                match dir {
+                    Facing::Up => Rectangle::new(pos.x, pos.y, size.x, size.y * THICKNESS),
+                    Facing::Down => Rectangle::new(
+                        pos.x,
+                        pos.y + size.y * (1. - THICKNESS),
+                        size.x,
+                        size.y * THICKNESS,
+                    ),
+                    Facing::Left => Rectangle::new(pos.x, pos.y, size.x * THICKNESS, size.y),
+                    Facing::Right => Rectangle::new(
+                        pos.x + size.x * (1. - THICKNESS),
+                        pos.y,
+                        size.x * THICKNESS,
+                        size.y,
+                    ),
+                }
+            }
+            Tile::Key | Tile::ColorKey(_) => {
+                Rectangle::new(pos.x + 4., pos.y + 4., size.x - 8., size.y - 8.)
+            }
         }
     }
 
     #[allow(dead_code)]
     pub fn set_facing(&mut self, facing: Facing) {
-        if let Tile::Spike(ref mut f) = *self {
-            *f = facing;
+        match *self {
+            Tile::Spike(ref mut f) | Tile::Spring(ref mut f, _) | Tile::OneWay(ref mut f) => {
+                *f = facing
+            }
+            // Conveyors only move sideways
+            Tile::Conveyor(ref mut f, _) if matches!(facing, Facing::Left | Facing::Right) => {
+                *f = facing
+            }
+            _ => {}
         }
     }
 
@@ -179,6 +303,13 @@ impl Tilemap {
         }
     }
 
+    pub fn get_tile_usize(&self, pos: (usize, usize)) -> Option<Tile> {
+        if pos.0 >= self.tilemap_size.x || pos.1 >= self.tilemap_size.y {
+            return None;
+        }
+        self.tiles.get(self.pos_to_index(pos)).copied()
+    }
+
     pub fn get_neigbor_tile_hboxes(&self, pos: Vec2<f32>) -> Vec<(Tile, Rectangle)> {
         let x = (pos.x / self.tile_width()).trunc() as usize;
         let y = (pos.y / self.tile_height()).trunc() as usize;
@@ -212,7 +343,18 @@ impl Tilemap {
     }
 
     pub fn render_tilemap(&self, ctx: &mut tetra::Context, assets: &Assets, color: Color) {
+        self.render_tilemap_except(ctx, assets, color, &[]);
+    }
+
+    pub fn render_tilemap_except(
+        &self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        color: Color,
+        hidden: &[(usize, usize)],
+    ) {
         self.run_for_each_tile(|(x, y), tile| match tile {
+            _ if hidden.contains(&(x, y)) => {}
             Tile::None => {}
             Tile::Solid => {
                 let pos = Vec2::new(x as f32, y as f32) * self.tile_size;
@@ -262,7 +404,7 @@ impl Tilemap {
                     .key
                     .draw(ctx, DrawParams::new().position(pos).color(color));
             }
-            Tile::Spring(_) => {
+            Tile::Spring(..) | Tile::OneWay(_) => {
                 let rect = tile.hbox(
                     Vec2::new(x as f32 * self.tile_width(), y as f32 * self.tile_height()),
                     self.tile_size(),
@@ -275,9 +417,294 @@ impl Tilemap {
                         .color(color),
                 );
             }
+            Tile::Checkpoint => self.draw_checkpoint(ctx, assets, (x, y), color, false),
+            Tile::Crumbling => self.draw_crumbling(ctx, assets, (x, y), Vec2::zero(), color),
+            Tile::ColorKey(key_color) => {
+                let pos = Vec2::new(x as f32, y as f32) * self.tile_size;
+                assets
+                    .key
+                    .draw(ctx, DrawParams::new().position(pos).color(color));
+                let center = pos + Vec2::new(self.tile_width() / 2., self.tile_height() * 0.875);
+                self.draw_key_color(ctx, assets, center, *key_color, color);
+            }
+            Tile::Lock(key_color) => {
+                let pos = Vec2::new(x as f32, y as f32) * self.tile_size;
+                let size = self.tile_size;
+                let border = size / 8.;
+                for rect in [
+                    Rectangle::new(pos.x, pos.y, size.x, border.y),
+                    Rectangle::new(pos.x, pos.y + size.y - border.y, size.x, border.y),
+                    Rectangle::new(pos.x, pos.y, border.x, size.y),
+                    Rectangle::new(pos.x + size.x - border.x, pos.y, border.x, size.y),
+                ] {
+                    Self::draw_rect(ctx, assets, rect, color);
+                }
+                self.draw_key_color(ctx, assets, pos + size / 2., *key_color, color);
+            }
+            Tile::Conveyor(facing, _) => self.draw_conveyor(ctx, assets, (x, y), *facing, color),
+            Tile::Switch(_, both_worlds) => {
+                self.draw_switch(ctx, assets, (x, y), *both_worlds, false, color)
+            }
+            Tile::SwitchBlock(_, solid) => {
+                self.draw_switch_block(ctx, assets, (x, y), *solid, color)
+            }
+            Tile::Teleporter(_) => {
+                let pos = Vec2::new(x as f32, y as f32) * self.tile_size;
+                let size = self.tile_size;
+                let line = size / 16.;
+                for inset in [0., 4.] {
+                    let corner = pos + line * inset;
+                    let side = size - line * inset * 2.;
+                    for rect in [
+                        Rectangle::new(corner.x, corner.y, side.x, line.y),
+                        Rectangle::new(corner.x, corner.y + side.y - line.y, side.x, line.y),
+                        Rectangle::new(corner.x, corner.y, line.x, side.y),
+                        Rectangle::new(corner.x + side.x - line.x, corner.y, line.x, side.y),
+                    ] {
+                        Self::draw_rect(ctx, assets, rect, color);
+                    }
+                }
+            }
+            Tile::Gravity(zone) => self.draw_gravity_zone(ctx, assets, (x, y), *zone, color),
+            Tile::Ice => {
+                // Outline with a diagonal glint
+                let pos = Vec2::new(x as f32, y as f32) * self.tile_size;
+                let size = self.tile_size;
+                let line = size / 16.;
+                for rect in [
+                    Rectangle::new(pos.x, pos.y, size.x, line.y),
+                    Rectangle::new(pos.x, pos.y + size.y - line.y, size.x, line.y),
+                    Rectangle::new(pos.x, pos.y, line.x, size.y),
+                    Rectangle::new(pos.x + size.x - line.x, pos.y, line.x, size.y),
+                ] {
+                    Self::draw_rect(ctx, assets, rect, color);
+                }
+                for i in 0..4 {
+                    let glint = pos + line * Vec2::new(9. - i as f32 * 2., 3. + i as f32 * 2.);
+                    let rect = Rectangle::new(glint.x, glint.y, line.x * 2., line.y * 2.);
+                    Self::draw_rect(ctx, assets, rect, color);
+                }
+            }
         });
     }
 
+    fn draw_rect(ctx: &mut tetra::Context, assets: &Assets, rect: Rectangle, color: Color) {
+        assets.pixel.draw(
+            ctx,
+            DrawParams::new()
+                .position(rect.top_left())
+                .scale(Vec2::new(rect.width, rect.height))
+                .color(color),
+        );
+    }
+
+    /// Draws a row of dots telling the color of a key or lock, centered on `center`.
+    fn draw_key_color(
+        &self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        center: Vec2<f32>,
+        key_color: KeyColor,
+        color: Color,
+    ) {
+        let dot = self.tile_size / 8.;
+        let count = key_color.dots();
+        let width = dot.x * (count * 2 - 1) as f32;
+        for i in 0..count {
+            let x = center.x - width / 2. + (i * 2) as f32 * dot.x;
+            let rect = Rectangle::new(x, center.y - dot.y / 2., dot.x, dot.y);
+            Self::draw_rect(ctx, assets, rect, color);
+        }
+    }
+
+    /// Draws a crumbling block as four smaller blocks, moved by `offset` to shake it.
+    pub fn draw_crumbling(
+        &self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        pos: (usize, usize),
+        offset: Vec2<f32>,
+        color: Color,
+    ) {
+        let origin = Vec2::new(pos.0 as f32, pos.1 as f32) * self.tile_size + offset;
+        let half = self.tile_size / 2.;
+        let gap = self.tile_size / 16.;
+        for i in 0..4 {
+            let corner = origin + Vec2::new((i % 2) as f32 * half.x, (i / 2) as f32 * half.y);
+            let rect = Rectangle::new(corner.x, corner.y, half.x - gap.x, half.y - gap.y);
+            Self::draw_rect(ctx, assets, rect, color);
+        }
+    }
+
+    /// Draws a conveyor as a belt with chevrons pointing where it moves.
+    fn draw_conveyor(
+        &self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        pos: (usize, usize),
+        facing: Facing,
+        color: Color,
+    ) {
+        let origin = Vec2::new(pos.0 as f32, pos.1 as f32) * self.tile_size;
+        let size = self.tile_size;
+        let dot = size / 8.;
+        for y in [origin.y, origin.y + size.y - dot.y] {
+            Self::draw_rect(
+                ctx,
+                assets,
+                Rectangle::new(origin.x, y, size.x, dot.y),
+                color,
+            );
+        }
+        let center_y = origin.y + size.y / 2. - dot.y / 2.;
+        for chevron in [2., 5.] {
+            for step in [-1., 0., 1.] {
+                let depth = if step == 0. { 1. } else { 0. };
+                let column = match facing {
+                    Facing::Left => 7. - chevron - depth,
+                    _ => chevron + depth,
+                };
+                let rect = Rectangle::new(
+                    origin.x + column * dot.x,
+                    center_y + step * dot.y,
+                    dot.x,
+                    dot.y,
+                );
+                Self::draw_rect(ctx, assets, rect, color);
+            }
+        }
+    }
+
+    /// Draws a switch as a button on a base, two buttons if it works in both worlds.
+    pub fn draw_switch(
+        &self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        pos: (usize, usize),
+        both_worlds: bool,
+        pressed: bool,
+        color: Color,
+    ) {
+        let origin = Vec2::new(pos.0 as f32, pos.1 as f32) * self.tile_size;
+        let size = self.tile_size;
+        let dot = size / 8.;
+        let base = Rectangle::new(origin.x, origin.y + size.y - dot.y * 2., size.x, dot.y * 2.);
+        Self::draw_rect(ctx, assets, base, color);
+        let height = if pressed { dot.y } else { dot.y * 3. };
+        let columns: &[f32] = if both_worlds { &[1., 5.] } else { &[3.] };
+        for column in columns {
+            let button = Rectangle::new(
+                origin.x + column * dot.x,
+                base.y - height,
+                dot.x * 2.,
+                height,
+            );
+            Self::draw_rect(ctx, assets, button, color);
+        }
+    }
+
+    /// Draws a switch block, filled when solid and as its corners when passable.
+    pub fn draw_switch_block(
+        &self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        pos: (usize, usize),
+        solid: bool,
+        color: Color,
+    ) {
+        let origin = Vec2::new(pos.0 as f32, pos.1 as f32) * self.tile_size;
+        let size = self.tile_size;
+        if solid {
+            let gap = size / 16.;
+            let rect = Rectangle::new(
+                origin.x + gap.x,
+                origin.y + gap.y,
+                size.x - gap.x * 2.,
+                size.y - gap.y * 2.,
+            );
+            Self::draw_rect(ctx, assets, rect, color);
+            return;
+        }
+        let dot = size / 8.;
+        for i in 0..4 {
+            let corner = Rectangle::new(
+                origin.x + (i % 2) as f32 * (size.x - dot.x),
+                origin.y + (i / 2) as f32 * (size.y - dot.y),
+                dot.x,
+                dot.y,
+            );
+            Self::draw_rect(ctx, assets, corner, color);
+        }
+    }
+
+    /// Draws the corners of a gravity zone with arrows showing where it sends the player.
+    fn draw_gravity_zone(
+        &self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        pos: (usize, usize),
+        zone: GravityZone,
+        color: Color,
+    ) {
+        let origin = Vec2::new(pos.0 as f32, pos.1 as f32) * self.tile_size;
+        let size = self.tile_size;
+        let dot = size / 8.;
+        for i in 0..4 {
+            let corner = Rectangle::new(
+                origin.x + (i % 2) as f32 * (size.x - dot.x),
+                origin.y + (i / 2) as f32 * (size.y - dot.y),
+                dot.x,
+                dot.y,
+            );
+            Self::draw_rect(ctx, assets, corner, color);
+        }
+        // Rows of the arrow tips, pointing up when negative
+        let arrows: &[(f32, f32)] = match zone {
+            GravityZone::Up => &[(3., -1.)],
+            GravityZone::Down => &[(4., 1.)],
+            GravityZone::Flip => &[(2., -1.), (5., 1.)],
+        };
+        for &(row, direction) in arrows {
+            for step in [-1., 0., 1.] {
+                let depth = if step == 0. { 0. } else { -direction };
+                let rect = Rectangle::new(
+                    origin.x + (3.5 + step) * dot.x,
+                    origin.y + (row + depth) * dot.y,
+                    dot.x,
+                    dot.y,
+                );
+                Self::draw_rect(ctx, assets, rect, color);
+            }
+        }
+    }
+
+    /// Draws a checkpoint flag, raised when it is the active checkpoint.
+    pub fn draw_checkpoint(
+        &self,
+        ctx: &mut tetra::Context,
+        assets: &Assets,
+        pos: (usize, usize),
+        color: Color,
+        active: bool,
+    ) {
+        let origin = Vec2::new(pos.0 as f32, pos.1 as f32) * self.tile_size;
+        let size = self.tile_size;
+        let pole = Rectangle::new(
+            origin.x + size.x * 0.25,
+            origin.y + size.y * 0.125,
+            size.x * 0.125,
+            size.y * 0.875,
+        );
+        let flag_y = if active {
+            pole.y
+        } else {
+            pole.bottom() - size.y * 0.375
+        };
+        let flag = Rectangle::new(pole.right(), flag_y, size.x * 0.5, size.y * 0.3125);
+        Self::draw_rect(ctx, assets, pole, color);
+        Self::draw_rect(ctx, assets, flag, color);
+    }
+
     pub fn resize(&mut self, new_size: Vec2<usize>) {
         if self.tilemap_size == new_size {
             return;
@@ -28,6 +28,27 @@ pub fn min_f32(x: f32, y: f32) -> f32 {
     }
 }
 
+/// Formats a duration in seconds as `m:ss.mmm`.
+pub fn format_time(seconds: f64) -> String {
+    let millis = (seconds * 1000.).round() as u64;
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Replaces every character that may not be valid in a file name.
+pub fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
 impl From<Color> for HsvColor {
     fn from(value: Color) -> Self {
         let r = value.r;
@@ -0,0 +1,444 @@
+use std::fmt;
+
+use tetra::{graphics::Rectangle, math::Vec2};
+
+use crate::{
+    level::Level,
+    player::Player,
+    tilemap::{Tile, Tilemap},
+    world::WorldMode,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    TilemapSizeMismatch,
+    TileSizeMismatch,
+    PositionsNotSet,
+    SpawnOutOfBounds,
+    DoorOutOfBounds,
+    SpawnInsideSolid,
+    SpawnOnSpike,
+    SpawnOnDoor,
+    DoorInsideSolid,
+    DoorOnSpike,
+    MedalTimesOutOfOrder,
+    LockWithoutKey,
+    EntityWithoutPath,
+    SwitchBlockWithoutSwitch,
+    TeleporterNotPaired,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub world: Option<WorldMode>,
+    pub tile: Option<(usize, usize)>,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, kind: DiagnosticKind) -> Diagnostic {
+        Diagnostic {
+            severity,
+            kind,
+            world: None,
+            tile: None,
+        }
+    }
+
+    fn at(mut self, world: WorldMode, tile: (usize, usize)) -> Diagnostic {
+        self.world = Some(world);
+        self.tile = Some(tile);
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            DiagnosticKind::TilemapSizeMismatch => "dark and light tilemaps have different sizes",
+            DiagnosticKind::TileSizeMismatch => "dark and light tilemaps have different tile sizes",
+            DiagnosticKind::PositionsNotSet => "spawn and door are both at their default position",
+            DiagnosticKind::SpawnOutOfBounds => "spawn is outside of the tilemap",
+            DiagnosticKind::DoorOutOfBounds => "door is outside of the tilemap",
+            DiagnosticKind::SpawnInsideSolid => "spawn is inside a solid tile",
+            DiagnosticKind::SpawnOnSpike => "spawn touches a spike",
+            DiagnosticKind::SpawnOnDoor => "spawn overlaps the door",
+            DiagnosticKind::DoorInsideSolid => "door is inside a solid tile",
+            DiagnosticKind::DoorOnSpike => "door touches a spike",
+            DiagnosticKind::LockWithoutKey => "lock has no key of its color in the level",
+            DiagnosticKind::EntityWithoutPath => "moving entity has less than two waypoints",
+            DiagnosticKind::SwitchBlockWithoutSwitch => {
+                "switch block has no switch of its group in its world"
+            }
+            DiagnosticKind::TeleporterNotPaired => {
+                "teleporter needs exactly one other teleporter with its pair id"
+            }
+            DiagnosticKind::MedalTimesOutOfOrder => {
+                "medal times should go from gold to bronze, fastest first"
+            }
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: ")?,
+            Severity::Error => write!(f, "error: ")?,
+        }
+        write!(f, "{}", self.kind)?;
+        if let (Some(world), Some((x, y))) = (self.world, self.tile) {
+            write!(f, " ({} world, tile {}, {})", world, x, y)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the position and contents of every tile overlapping `rect`.
+fn tiles_under(tilemap: &Tilemap, rect: &Rectangle) -> Vec<((usize, usize), Tile, Rectangle)> {
+    let tile_size = tilemap.tile_size();
+    let min_x = (rect.left() / tile_size.x).floor().max(0.) as usize;
+    let min_y = (rect.top() / tile_size.y).floor().max(0.) as usize;
+    let max_x = (rect.right() / tile_size.x).ceil().max(0.) as usize;
+    let max_y = (rect.bottom() / tile_size.y).ceil().max(0.) as usize;
+    let mut tiles = vec![];
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            if let Some(tile) = tilemap.get_tile_usize((x, y)) {
+                let pos = Vec2::new(x as f32, y as f32) * tile_size;
+                tiles.push(((x, y), tile, tile.hbox(pos, tile_size)));
+            }
+        }
+    }
+    tiles
+}
+
+fn contains(outer: &Rectangle, inner: &Rectangle) -> bool {
+    inner.left() >= outer.left()
+        && inner.right() <= outer.right()
+        && inner.top() >= outer.top()
+        && inner.bottom() <= outer.bottom()
+}
+
+pub fn validate(level: &Level) -> Vec<Diagnostic> {
+    use DiagnosticKind::*;
+    use Severity::*;
+
+    let mut diagnostics = vec![];
+    let dark = &level.dark_tilemap;
+    let light = &level.light_tilemap;
+    if dark.size() != light.size() {
+        diagnostics.push(Diagnostic::new(Error, TilemapSizeMismatch));
+    }
+    if dark.tile_size() != light.tile_size() {
+        diagnostics.push(Diagnostic::new(Error, TileSizeMismatch));
+    }
+    if level.spawn_pos == Vec2::zero() && level.end_pos == Vec2::zero() {
+        diagnostics.push(Diagnostic::new(Warning, PositionsNotSet));
+    }
+    if !level.medals.is_ordered() {
+        diagnostics.push(Diagnostic::new(Warning, MedalTimesOutOfOrder));
+    }
+    for entity in &level.entities {
+        if entity.waypoints.len() < 2 {
+            let mut diagnostic = Diagnostic::new(Warning, EntityWithoutPath);
+            diagnostic.world = Some(entity.world);
+            if let Some(first) = entity.waypoints.first() {
+                let tile = *first / dark.tile_size();
+                diagnostic.tile = Some((tile.x as usize, tile.y as usize));
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    let spawn_rect = Player::new(level.spawn_pos).get_hbox();
+    let door_size = dark.tile_size();
+    let door_rect = Rectangle::new(level.end_pos.x, level.end_pos.y, door_size.x, door_size.y);
+    let spawn_in_bounds = contains(&dark.rect(), &spawn_rect);
+    let door_in_bounds = contains(&dark.rect(), &door_rect);
+    if !spawn_in_bounds {
+        diagnostics.push(Diagnostic::new(Error, SpawnOutOfBounds));
+    }
+    if !door_in_bounds {
+        diagnostics.push(Diagnostic::new(Error, DoorOutOfBounds));
+    }
+    if spawn_rect.intersects(&door_rect) {
+        diagnostics.push(Diagnostic::new(Warning, SpawnOnDoor));
+    }
+
+    let mut key_colors = vec![];
+    for tilemap in [dark, light] {
+        tilemap.run_for_each_tile(|_, tile| {
+            if let Tile::ColorKey(color) = tile {
+                key_colors.push(*color);
+            }
+        });
+    }
+    for (world, tilemap) in [(WorldMode::Dark, dark), (WorldMode::Light, light)] {
+        tilemap.run_for_each_tile(|pos, tile| {
+            if matches!(tile, Tile::Lock(color) if !key_colors.contains(color)) {
+                diagnostics.push(Diagnostic::new(Warning, LockWithoutKey).at(world, pos));
+            }
+        });
+    }
+
+    // Switches set to work in both worlds reach the blocks of the other world too
+    let mut switch_groups = vec![];
+    for (world, tilemap) in [(WorldMode::Dark, dark), (WorldMode::Light, light)] {
+        tilemap.run_for_each_tile(|_, tile| {
+            if let Tile::Switch(group, both_worlds) = *tile {
+                switch_groups.push((world, group));
+                if both_worlds {
+                    switch_groups.push((world.next(), group));
+                }
+            }
+        });
+    }
+    for (world, tilemap) in [(WorldMode::Dark, dark), (WorldMode::Light, light)] {
+        tilemap.run_for_each_tile(|pos, tile| {
+            if matches!(*tile, Tile::SwitchBlock(group, _) if !switch_groups.contains(&(world, group)))
+            {
+                diagnostics.push(Diagnostic::new(Warning, SwitchBlockWithoutSwitch).at(world, pos));
+            }
+        });
+    }
+
+    let mut teleporters = vec![];
+    for (world, tilemap) in [(WorldMode::Dark, dark), (WorldMode::Light, light)] {
+        tilemap.run_for_each_tile(|pos, tile| {
+            if let Tile::Teleporter(pair) = *tile {
+                teleporters.push((world, pos, pair));
+            }
+        });
+    }
+    for (world, pos, pair) in &teleporters {
+        if teleporters.iter().filter(|(_, _, p)| p == pair).count() != 2 {
+            diagnostics.push(Diagnostic::new(Error, TeleporterNotPaired).at(*world, *pos));
+        }
+    }
+
+    // The player always spawns in the dark world
+    if spawn_in_bounds {
+        for (pos, tile, hbox) in tiles_under(dark, &spawn_rect) {
+            match tile {
+                _ if tile.is_solid() => diagnostics
+                    .push(Diagnostic::new(Error, SpawnInsideSolid).at(WorldMode::Dark, pos)),
+                Tile::Spike(_) if hbox.intersects(&spawn_rect) => {
+                    diagnostics.push(Diagnostic::new(Error, SpawnOnSpike).at(WorldMode::Dark, pos))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // The door can be reached from either world, so it is only unreachable when both are blocked
+    if door_in_bounds {
+        let mut blocked = vec![];
+        let mut blocked_worlds = 0;
+        for (world, tilemap) in [(WorldMode::Dark, dark), (WorldMode::Light, light)] {
+            let blocked_before = blocked.len();
+            for (pos, tile, hbox) in tiles_under(tilemap, &door_rect) {
+                match tile {
+                    _ if tile.is_solid() => {
+                        blocked.push(Diagnostic::new(Error, DoorInsideSolid).at(world, pos));
+                    }
+                    Tile::Spike(_) if hbox.intersects(&door_rect) => {
+                        diagnostics.push(Diagnostic::new(Warning, DoorOnSpike).at(world, pos));
+                    }
+                    _ => {}
+                }
+            }
+            if blocked.len() > blocked_before {
+                blocked_worlds += 1;
+            }
+        }
+        if blocked_worlds == 2 {
+            diagnostics.extend(blocked);
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod test {
+    use super::{validate, DiagnosticKind, Severity};
+    use crate::{level::Level, world::WorldMode};
+
+    fn kinds(level: &Level) -> Vec<(Severity, DiagnosticKind)> {
+        validate(level)
+            .into_iter()
+            .map(|d| (d.severity, d.kind))
+            .collect()
+    }
+
+    #[test]
+    fn valid_level() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 1
+            door: 3 1
+            dark:
+            #####
+            #...#
+            #####
+            light:
+            .....
+            .....
+            .....
+            ",
+        )
+        .unwrap();
+        assert!(validate(&level).is_empty());
+    }
+
+    #[test]
+    fn broken_level() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 1
+            door: 3 1
+            dark:
+            #####
+            ##..#
+            #####
+            light:
+            .....
+            ...^.
+            .....
+            ",
+        )
+        .unwrap();
+        let diagnostics = validate(&level);
+        assert_eq!(
+            kinds(&level),
+            vec![
+                (Severity::Error, DiagnosticKind::SpawnInsideSolid),
+                (Severity::Warning, DiagnosticKind::DoorOnSpike),
+            ]
+        );
+        assert_eq!(diagnostics[0].world, Some(WorldMode::Dark));
+        assert_eq!(diagnostics[0].tile, Some((1, 1)));
+        assert_eq!(diagnostics[1].world, Some(WorldMode::Light));
+        assert_eq!(diagnostics[1].tile, Some((3, 1)));
+    }
+
+    #[test]
+    fn switch_blocks_need_a_switch() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 1
+            door: 5 1
+            group: light 3 1 2
+            dark:
+            #######
+            #..w..#
+            #######
+            light:
+            .......
+            .S.w...
+            .......
+            ",
+        )
+        .unwrap();
+        let diagnostics = validate(&level);
+        assert_eq!(
+            kinds(&level),
+            vec![(Severity::Warning, DiagnosticKind::SwitchBlockWithoutSwitch)]
+        );
+        assert_eq!(diagnostics[0].world, Some(WorldMode::Light));
+        assert_eq!(diagnostics[0].tile, Some((3, 1)));
+    }
+
+    #[test]
+    fn teleporters_come_in_pairs() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 1
+            door: 5 1
+            pair: dark 3 1 1
+            dark:
+            #######
+            #.tt..#
+            #######
+            light:
+            .......
+            ...t...
+            .......
+            ",
+        )
+        .unwrap();
+        let diagnostics = validate(&level);
+        assert_eq!(
+            kinds(&level),
+            vec![(Severity::Error, DiagnosticKind::TeleporterNotPaired)]
+        );
+        assert_eq!(diagnostics[0].world, Some(WorldMode::Dark));
+        assert_eq!(diagnostics[0].tile, Some((3, 1)));
+    }
+
+    #[test]
+    fn door_walled_in_both_worlds() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 1
+            door: 3 1
+            dark:
+            #####
+            #..##
+            #####
+            light:
+            .....
+            ...#.
+            .....
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            kinds(&level),
+            vec![
+                (Severity::Error, DiagnosticKind::DoorInsideSolid),
+                (Severity::Error, DiagnosticKind::DoorInsideSolid),
+            ]
+        );
+    }
+
+    #[test]
+    fn any_solid_tile_blocks_spawn_and_door() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 1
+            door: 3 1
+            dark:
+            #####
+            #%rR#
+            #####
+            light:
+            .....
+            ...i.
+            .....
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            kinds(&level),
+            vec![
+                (Severity::Error, DiagnosticKind::SpawnInsideSolid),
+                (Severity::Error, DiagnosticKind::DoorInsideSolid),
+                (Severity::Error, DiagnosticKind::DoorInsideSolid),
+            ]
+        );
+    }
+}
@@ -1,20 +1,23 @@
 use core::fmt;
+use std::{hash::Hash, rc::Rc};
 
 use keyframe::{ease, functions::EaseOutQuart, num_traits::clamp};
+use serde::{Deserialize, Serialize};
 use tetra::{
     graphics::{self, BlendState, Color, DrawParams, Rectangle},
-    input::{self, Key},
     math::Vec2,
 };
 
 use crate::{
+    entity::{Entity, EntityKind},
+    input::InputFrame,
     level::Level,
-    player::Player,
-    tilemap::{Tile, Tilemap},
+    player::{Player, Surface},
+    tilemap::{Facing, GravityZone, KeyColor, Tile, Tilemap},
     Assets,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum WorldMode {
     Dark,
     Light,
@@ -42,6 +45,7 @@ impl WorldMode {
     }
 }
 
+#[derive(Clone)]
 struct OthersideAnimator {
     size_anim_progress: f32,
     full_circle_size: f32,
@@ -99,10 +103,43 @@ impl OthersideAnimator {
     }
 }
 
+/// Progress kept when dying after touching a checkpoint.
+#[derive(Clone)]
+struct Checkpoint {
+    mode: WorldMode,
+    tile: (usize, usize),
+    dark_keys: Vec<(usize, usize)>,
+    light_keys: Vec<(usize, usize)>,
+    switched: Vec<(WorldMode, u8)>,
+    fall_direction: f32,
+}
+
+/// Crumbling tile the player stood on, counting the ticks since.
+#[derive(Clone)]
+struct CrumblingTile {
+    mode: WorldMode,
+    tile: (usize, usize),
+    ticks: u32,
+}
+
+/// Teleporter tile, sending the player to the other one with the same pair id.
+struct Teleporter {
+    mode: WorldMode,
+    tile: (usize, usize),
+    pair: u8,
+}
+
+impl CrumblingTile {
+    fn is_broken(&self) -> bool {
+        self.ticks >= World::CRUMBLE_TICKS
+    }
+}
+
+#[derive(Clone)]
 pub struct World {
     player: Player,
-    dark_tilemap: Tilemap,
-    light_tilemap: Tilemap,
+    dark_tilemap: Rc<Tilemap>,
+    light_tilemap: Rc<Tilemap>,
     mode: WorldMode,
     spawn_pos: Vec2<f32>,
     end_rect: Rectangle,
@@ -110,25 +147,75 @@ pub struct World {
     light_keys: Vec<(usize, usize)>,
     keys_amount: usize,
     got_keys: usize,
+    deaths: usize,
+    checkpoint: Option<Checkpoint>,
+    crumbling: Vec<CrumblingTile>,
+    /// Switch groups toggled an odd number of times, in each world.
+    switched: Vec<(WorldMode, u8)>,
+    /// Switches the player touched last tick, which only toggle again once left.
+    pressed_switches: Vec<(WorldMode, (usize, usize))>,
+    /// Every teleporter of the level with its pair id.
+    teleporters: Rc<Vec<Teleporter>>,
+    /// Teleporter the player came out of, which only works again once left.
+    teleported_to: Option<(WorldMode, (usize, usize))>,
+    /// Gravity zones the player was in last tick, so flipping zones only flip once.
+    gravity_zones: Vec<(WorldMode, (usize, usize))>,
+    entities: Rc<Vec<Entity>>,
+    entity_ticks: u64,
+    previous_player_pos: Vec2<f32>,
     win: bool,
     otherside: OthersideAnimator,
 }
 
 impl World {
+    /// Physics run at a fixed rate so the game plays the same regardless of the framerate.
+    pub const TICK_RATE: f32 = 60.;
+    pub const TICK: f32 = 1. / Self::TICK_RATE;
+    /// Ticks between standing on a crumbling block and it breaking.
+    const CRUMBLE_TICKS: u32 = 30;
+    /// Ticks a crumbling block stays broken before coming back.
+    const CRUMBLE_RESPAWN_TICKS: u32 = 180;
+    /// Radians saws turn every tick.
+    const SAW_SPIN: f32 = 0.2;
+
+    pub fn ticks_to_secs(ticks: u64) -> f64 {
+        ticks as f64 / Self::TICK_RATE as f64
+    }
+
+    pub fn secs_to_ticks(secs: f64) -> u64 {
+        (secs * Self::TICK_RATE as f64).round() as u64
+    }
+
     pub fn new(level: Level) -> World {
         let Level {
             dark_tilemap,
             light_tilemap,
             spawn_pos,
             end_pos,
+            entities,
             ..
         } = level;
         let tile_size = dark_tilemap.tile_size();
         let keys_amount = dark_tilemap.keys_amount() + light_tilemap.keys_amount();
+        let mut teleporters = vec![];
+        for (mode, tilemap) in [
+            (WorldMode::Dark, &dark_tilemap),
+            (WorldMode::Light, &light_tilemap),
+        ] {
+            tilemap.run_for_each_tile(|pos, tile| {
+                if let Tile::Teleporter(pair) = tile {
+                    teleporters.push(Teleporter {
+                        mode,
+                        tile: pos,
+                        pair: *pair,
+                    });
+                }
+            });
+        }
         World {
             player: Player::new(spawn_pos),
-            dark_tilemap,
-            light_tilemap,
+            dark_tilemap: Rc::new(dark_tilemap),
+            light_tilemap: Rc::new(light_tilemap),
             mode: WorldMode::Dark,
             spawn_pos,
             end_rect: Rectangle::new(end_pos.x, end_pos.y, tile_size.x, tile_size.y),
@@ -137,6 +224,17 @@ impl World {
             light_keys: Vec::new(),
             keys_amount,
             got_keys: 0,
+            deaths: 0,
+            checkpoint: None,
+            crumbling: Vec::new(),
+            switched: Vec::new(),
+            pressed_switches: Vec::new(),
+            teleporters: Rc::new(teleporters),
+            teleported_to: None,
+            gravity_zones: Vec::new(),
+            entities: Rc::new(entities),
+            entity_ticks: 0,
+            previous_player_pos: Player::new(spawn_pos).get_hbox().center(),
             otherside: OthersideAnimator::new(),
         }
     }
@@ -144,51 +242,251 @@ impl World {
     pub fn reset(&mut self) {
         self.win = false;
         self.player = Player::new(self.spawn_pos);
+        self.previous_player_pos = self.player_pos();
         self.mode = WorldMode::Dark;
         self.otherside = OthersideAnimator::new();
-        for pos in &self.dark_keys {
-            self.dark_tilemap.set_tile_usize(*pos, Tile::Key);
+        self.dark_keys.clear();
+        self.light_keys.clear();
+        self.got_keys = 0;
+        self.checkpoint = None;
+        self.crumbling.clear();
+        self.switched.clear();
+        self.pressed_switches.clear();
+        self.teleported_to = None;
+        self.gravity_zones.clear();
+        self.entity_ticks = 0;
+    }
+
+    /// Puts the player back at the last checkpoint touched, restoring the keys collected when
+    /// it was touched, or restarts the level if there is none.
+    fn respawn(&mut self) {
+        let Some(checkpoint) = self.checkpoint.clone() else {
+            self.reset();
+            return;
+        };
+        let tile_size = self.dark_tilemap.tile_size();
+        let pos = Vec2::new(checkpoint.tile.0 as f32, checkpoint.tile.1 as f32) * tile_size;
+        self.player = Player::new(pos);
+        self.player.on_world_change(checkpoint.mode);
+        self.player.set_fall_direction(checkpoint.fall_direction);
+        self.previous_player_pos = self.player_pos();
+        self.mode = checkpoint.mode;
+        self.otherside = OthersideAnimator::new();
+        self.otherside.world_change(checkpoint.mode);
+        self.crumbling.clear();
+        self.switched = checkpoint.switched;
+        self.pressed_switches.clear();
+        self.teleported_to = None;
+        self.gravity_zones.clear();
+        self.dark_keys = checkpoint.dark_keys;
+        self.light_keys = checkpoint.light_keys;
+        self.got_keys = self
+            .collected_tiles()
+            .filter(|tile| *tile == Some(Tile::Key))
+            .count();
+    }
+
+    fn die(&mut self) {
+        self.deaths += 1;
+        self.respawn();
+    }
+
+    /// Tiles of the keys and colored keys collected in either world.
+    fn collected_tiles(&self) -> impl Iterator<Item = Option<Tile>> + '_ {
+        let dark = self
+            .dark_keys
+            .iter()
+            .map(|pos| self.dark_tilemap.get_tile_usize(*pos));
+        let light = self
+            .light_keys
+            .iter()
+            .map(|pos| self.light_tilemap.get_tile_usize(*pos));
+        dark.chain(light)
+    }
+
+    /// Colors of the colored keys collected in either world.
+    fn unlocked_colors(&self) -> Vec<KeyColor> {
+        self.collected_tiles()
+            .filter_map(|tile| match tile {
+                Some(Tile::ColorKey(color)) => Some(color),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The other end of a teleporter, if it has exactly one.
+    fn teleporter_pair(
+        &self,
+        mode: WorldMode,
+        tile: (usize, usize),
+        pair: u8,
+    ) -> Option<(WorldMode, (usize, usize))> {
+        let mut others = self
+            .teleporters
+            .iter()
+            .filter(|t| t.pair == pair && (t.mode, t.tile) != (mode, tile));
+        match (others.next(), others.next()) {
+            (Some(other), None) => Some((other.mode, other.tile)),
+            _ => None,
         }
-        for pos in &self.light_keys {
-            self.light_tilemap.set_tile_usize(*pos, Tile::Key);
+    }
+
+    fn is_switched(&self, mode: WorldMode, group: u8) -> bool {
+        self.switched.contains(&(mode, group))
+    }
+
+    fn toggle_group(switched: &mut Vec<(WorldMode, u8)>, mode: WorldMode, group: u8) {
+        match switched.iter().position(|s| *s == (mode, group)) {
+            Some(i) => {
+                switched.remove(i);
+            }
+            None => switched.push((mode, group)),
         }
-        self.got_keys = 0;
+    }
+
+    /// Advances the timers of crumbling tiles and restores the ones that are done, unless the
+    /// player is in the way.
+    fn update_crumbling(&mut self) {
+        let player_rect = self.player.get_hbox();
+        let tile_size = self.dark_tilemap.tile_size();
+        let mode = self.mode;
+        self.crumbling.retain_mut(|crumbling| {
+            crumbling.ticks += 1;
+            if crumbling.ticks < Self::CRUMBLE_TICKS + Self::CRUMBLE_RESPAWN_TICKS {
+                return true;
+            }
+            let (x, y) = crumbling.tile;
+            let rect = Rectangle::new(
+                x as f32 * tile_size.x,
+                y as f32 * tile_size.y,
+                tile_size.x,
+                tile_size.y,
+            );
+            crumbling.mode == mode && rect.intersects(&player_rect)
+        });
+    }
+
+    /// Carries the player standing on moving platforms and collides them with the platforms,
+    /// returning the areas of the saws after they moved.
+    fn update_entities(&mut self) -> Vec<Rectangle> {
+        let tile_size = self.dark_tilemap.tile_size();
+        let entities = Rc::clone(&self.entities);
+        let mut saws = vec![];
+        for entity in entities.iter().filter(|e| e.world == self.mode) {
+            let before = entity.rect(entity.position(self.entity_ticks), tile_size);
+            let after = entity.rect(entity.position(self.entity_ticks + 1), tile_size);
+            match entity.kind {
+                EntityKind::Platform => {
+                    if self.player.is_landing_on(&before) {
+                        self.player.carry(after.top_left() - before.top_left());
+                    }
+                    self.player.solve_collision_y(&after, Surface::Normal);
+                    self.player.solve_collision_x(&after);
+                }
+                EntityKind::Saw => saws.push(after),
+            }
+        }
+        self.entity_ticks += 1;
+        saws
     }
 
     pub fn player_pos(&self) -> Vec2<f32> {
         self.player.get_hbox().center()
     }
 
+    /// Player position between the last two ticks, `alpha` being the progress towards the
+    /// next tick.
+    pub fn interpolated_player_pos(&self, alpha: f32) -> Vec2<f32> {
+        Vec2::lerp(self.previous_player_pos, self.player_pos(), alpha)
+    }
+
     pub fn get_world_rect(&self) -> Rectangle {
         self.dark_tilemap.rect()
     }
 
-    pub fn update(&mut self, ctx: &mut tetra::Context) {
-        let dt = tetra::time::get_delta_time(ctx).as_secs_f32();
+    /// Runs one tick of game logic. This doesn't need a `tetra::Context`, so it can be driven
+    /// by tests and tools as well as by `GameScene`.
+    pub fn step(&mut self, input: &InputFrame, dt: f32) {
         if self.win {
             return;
         }
-        self.player.update(ctx);
+        self.previous_player_pos = self.player_pos();
+        self.otherside.update(dt);
+        if input.reset {
+            self.reset();
+            return;
+        }
+        self.player.update(input);
+        self.update_crumbling();
+        let saws = self.update_entities();
 
+        let unlocked = self.unlocked_colors();
+        let broken = self
+            .crumbling
+            .iter()
+            .filter(|c| c.mode == self.mode && c.is_broken())
+            .map(|c| c.tile)
+            .collect::<Vec<_>>();
+        let switched = self
+            .switched
+            .iter()
+            .filter(|(mode, _)| *mode == self.mode)
+            .map(|(_, group)| *group)
+            .collect::<Vec<_>>();
         let (tilemap, keys) = match self.mode {
-            WorldMode::Dark => (&mut self.dark_tilemap, &mut self.dark_keys),
-            WorldMode::Light => (&mut self.light_tilemap, &mut self.light_keys),
+            WorldMode::Dark => (&self.dark_tilemap, &mut self.dark_keys),
+            WorldMode::Light => (&self.light_tilemap, &mut self.light_keys),
         };
 
         let neighbors = tilemap.get_neigbor_tile_hboxes(self.player.get_hbox().center());
         let mut spikes = vec![];
         let mut springs = vec![];
         let mut collected_keys = vec![];
+        let mut checkpoints = vec![];
+        let mut stood_on = vec![];
+        let mut switches = vec![];
+        let mut teleporters = vec![];
+        let mut gravity_zones = vec![];
         for (tile, rect) in &neighbors {
             match tile {
                 Tile::None => continue,
-                Tile::Solid => {
-                    self.player.solve_collision_y(rect);
+                Tile::Lock(color) if unlocked.contains(color) => continue,
+                Tile::SwitchBlock(group, solid) if *solid == switched.contains(group) => continue,
+                Tile::Solid | Tile::Lock(_) | Tile::SwitchBlock(..) => {
+                    self.player.solve_collision_y(rect, Surface::Normal);
+                    self.player.solve_collision_x(rect);
+                }
+                Tile::Crumbling => {
+                    let coords = rect.top_left() / tilemap.tile_size();
+                    let coords = (coords.x as usize, coords.y as usize);
+                    if broken.contains(&coords) {
+                        continue;
+                    }
+                    if self.player.is_landing_on(rect) {
+                        stood_on.push(coords);
+                    }
+                    self.player.solve_collision_y(rect, Surface::Normal);
+                    self.player.solve_collision_x(rect);
+                }
+                Tile::Conveyor(facing, speed) => {
+                    let speed = *speed as f32 / 10.;
+                    let speed = match facing {
+                        Facing::Left => -speed,
+                        _ => speed,
+                    };
+                    self.player.solve_collision_y(rect, Surface::Moving(speed));
+                    self.player.solve_collision_x(rect);
+                }
+                Tile::Ice => {
+                    self.player.solve_collision_y(rect, Surface::Ice);
                     self.player.solve_collision_x(rect);
                 }
                 Tile::Spike(_) => {
                     spikes.push(rect);
                 }
+                Tile::OneWay(facing) => {
+                    self.player.solve_one_way_collision(rect, *facing);
+                }
                 Tile::Portal(axis) => {
                     if self.player.can_traverse_portal(rect, *axis) {
                         self.mode.switch();
@@ -196,63 +494,262 @@ impl World {
                         self.otherside.world_change(self.mode);
                     }
                 }
-                Tile::Key => {
-                    collected_keys.push(rect);
+                Tile::Key | Tile::ColorKey(_) => {
+                    let coords = rect.top_left() / tilemap.tile_size();
+                    let coords = (coords.x as usize, coords.y as usize);
+                    if !keys.contains(&coords) {
+                        collected_keys.push((coords, rect, *tile));
+                    }
+                }
+                Tile::Spring(dir, force) => {
+                    springs.push((rect, *dir, *force));
+                }
+                Tile::Checkpoint => {
+                    let coords = rect.top_left() / tilemap.tile_size();
+                    checkpoints.push(((coords.x as usize, coords.y as usize), rect));
+                }
+                Tile::Gravity(zone) => {
+                    let coords = rect.top_left() / tilemap.tile_size();
+                    gravity_zones.push(((coords.x as usize, coords.y as usize), rect, *zone));
                 }
-                Tile::Spring(dir) => {
-                    springs.push((rect, dir));
+                Tile::Teleporter(pair) => {
+                    let coords = rect.top_left() / tilemap.tile_size();
+                    teleporters.push(((coords.x as usize, coords.y as usize), rect, *pair));
+                }
+                Tile::Switch(group, both_worlds) => {
+                    let coords = rect.top_left() / tilemap.tile_size();
+                    let coords = (coords.x as usize, coords.y as usize);
+                    switches.push((coords, rect, *group, *both_worlds));
                 }
             }
         }
 
         self.player.post_update();
-        self.otherside.update(dt);
+        for coords in stood_on {
+            let mode = self.mode;
+            if !self
+                .crumbling
+                .iter()
+                .any(|c| c.mode == mode && c.tile == coords)
+            {
+                self.crumbling.push(CrumblingTile {
+                    mode,
+                    tile: coords,
+                    ticks: 0,
+                });
+            }
+        }
 
         let player_rect = self.player.get_hbox();
         if self.got_keys == self.keys_amount && player_rect.intersects(&self.end_rect) {
             self.win = true;
             return;
         }
-        collected_keys.into_iter().for_each(|k| {
+        collected_keys.into_iter().for_each(|(coords, k, tile)| {
             if player_rect.intersects(k) {
-                let coords = k.top_left() / tilemap.tile_size();
-                let coords = (coords.x as usize, coords.y as usize);
                 keys.push(coords);
-                tilemap.set_tile_usize(coords, Tile::None);
-                self.got_keys += 1;
+                // Colored keys only open locks, the door needs every plain key
+                if tile == Tile::Key {
+                    self.got_keys += 1;
+                }
             }
         });
-        if spikes.into_iter().any(|s| s.intersects(&player_rect)) {
-            self.reset();
+        let mode = self.mode;
+        let touched = switches
+            .into_iter()
+            .filter(|(_, rect, _, _)| rect.intersects(&player_rect))
+            .collect::<Vec<_>>();
+        for &(coords, _, group, both_worlds) in &touched {
+            if !self.pressed_switches.contains(&(mode, coords)) {
+                Self::toggle_group(&mut self.switched, mode, group);
+                if both_worlds {
+                    Self::toggle_group(&mut self.switched, mode.next(), group);
+                }
+            }
+        }
+        self.pressed_switches = touched
+            .into_iter()
+            .map(|(coords, ..)| (mode, coords))
+            .collect();
+        if spikes
+            .into_iter()
+            .chain(&saws)
+            .any(|s| s.intersects(&player_rect))
+        {
+            self.die();
             return;
         }
-        springs.into_iter().for_each(|(rect, dir)| {
+        springs.into_iter().for_each(|(rect, dir, force)| {
             if player_rect.intersects(rect) {
-                self.player.on_spring(*dir);
+                self.player.on_spring(dir, force as f32);
+            }
+        });
+        // Touching a checkpoint again commits what was collected since
+        for (coords, rect) in checkpoints {
+            if player_rect.intersects(rect) {
+                self.checkpoint = Some(Checkpoint {
+                    mode: self.mode,
+                    tile: coords,
+                    dark_keys: self.dark_keys.clone(),
+                    light_keys: self.light_keys.clone(),
+                    switched: self.switched.clone(),
+                    fall_direction: self.player.fall_direction(),
+                });
+            }
+        }
+
+        let center = player_rect.center();
+        let inside = gravity_zones
+            .into_iter()
+            .filter(|(_, rect, _)| rect.contains_point(center))
+            .collect::<Vec<_>>();
+        for &(coords, _, zone) in &inside {
+            match zone {
+                GravityZone::Down => self.player.set_fall_direction(Player::FALL_DOWN),
+                GravityZone::Up => self.player.set_fall_direction(Player::FALL_UP),
+                GravityZone::Flip if !self.gravity_zones.contains(&(mode, coords)) => {
+                    let direction = -self.player.fall_direction();
+                    self.player.set_fall_direction(direction);
+                }
+                GravityZone::Flip => {}
+            }
+        }
+        self.gravity_zones = inside
+            .into_iter()
+            .map(|(coords, ..)| (mode, coords))
+            .collect();
+
+        // Coming out of a teleporter doesn't send the player back until they left it
+        let tile_size = tilemap.tile_size();
+        if let Some((to_mode, (x, y))) = self.teleported_to {
+            let rect = Rectangle::new(
+                x as f32 * tile_size.x,
+                y as f32 * tile_size.y,
+                tile_size.x,
+                tile_size.y,
+            );
+            if to_mode != mode || !rect.intersects(&player_rect) {
+                self.teleported_to = None;
             }
+        }
+        let entered = teleporters.into_iter().find(|(coords, rect, _)| {
+            rect.contains_point(center) && self.teleported_to != Some((mode, *coords))
         });
+        if let Some((coords, rect, pair)) = entered {
+            if let Some((to_mode, to)) = self.teleporter_pair(mode, coords, pair) {
+                let exit = Vec2::new(to.0 as f32, to.1 as f32) * tile_size;
+                self.player.carry(exit - rect.top_left());
+                // Don't draw the player sliding across the map to the exit
+                self.previous_player_pos = self.player_pos();
+                if to_mode != mode {
+                    self.mode = to_mode;
+                    self.player.on_world_change(to_mode);
+                    self.otherside.world_change(to_mode);
+                }
+                self.teleported_to = Some((to_mode, to));
+                return;
+            }
+        }
+
         let tilemap_rect = tilemap.rect();
 
-        if input::is_key_pressed(ctx, Key::R) || !tilemap_rect.intersects(&player_rect) {
-            self.reset();
+        if !tilemap_rect.intersects(&player_rect) {
+            self.die();
         }
     }
 
-    pub fn draw(&self, ctx: &mut tetra::Context, assets: &Assets) {
+    pub fn draw(&self, ctx: &mut tetra::Context, assets: &Assets, alpha: f32) {
         graphics::set_blend_state(ctx, BlendState::add(false));
         assets
             .shader
             .set_uniform(ctx, "u_circle_radius", self.otherside.get_circle_size());
-        let player_hbox = self.player.get_hbox();
-        assets
-            .shader
-            .set_uniform(ctx, "u_circle_pos", player_hbox.center());
+        let player_pos = self.interpolated_player_pos(alpha);
+        assets.shader.set_uniform(ctx, "u_circle_pos", player_pos);
+        self.draw_player(ctx, assets, alpha, Color::WHITE);
+        let door = if self.got_keys == self.keys_amount {
+            &assets.door
+        } else {
+            &assets.door_locked
+        };
+        door.draw(ctx, DrawParams::new().position(self.end_rect.top_left()));
+        let unlocked = self.unlocked_colors();
+        for (mode, tilemap, keys, color) in [
+            (
+                WorldMode::Dark,
+                &self.dark_tilemap,
+                &self.dark_keys,
+                Color::RED,
+            ),
+            (
+                WorldMode::Light,
+                &self.light_tilemap,
+                &self.light_keys,
+                Color::BLUE,
+            ),
+        ] {
+            let mut hidden = keys.clone();
+            let mut switches = vec![];
+            let mut blocks = vec![];
+            if !unlocked.is_empty() || !self.switched.is_empty() {
+                tilemap.run_for_each_tile(|pos, tile| match *tile {
+                    Tile::Lock(c) if unlocked.contains(&c) => hidden.push(pos),
+                    Tile::Switch(group, both_worlds) if self.is_switched(mode, group) => {
+                        hidden.push(pos);
+                        switches.push((pos, both_worlds));
+                    }
+                    Tile::SwitchBlock(group, solid) if self.is_switched(mode, group) => {
+                        hidden.push(pos);
+                        blocks.push((pos, !solid));
+                    }
+                    _ => {}
+                });
+            }
+            let crumbling = self
+                .crumbling
+                .iter()
+                .filter(|c| c.mode == mode)
+                .collect::<Vec<_>>();
+            hidden.extend(crumbling.iter().map(|c| c.tile));
+            let active_checkpoint = self.checkpoint.as_ref().filter(|c| c.mode == mode);
+            if let Some(checkpoint) = active_checkpoint {
+                hidden.push(checkpoint.tile);
+            }
+            tilemap.render_tilemap_except(ctx, assets, color, &hidden);
+            if let Some(checkpoint) = active_checkpoint {
+                tilemap.draw_checkpoint(ctx, assets, checkpoint.tile, color, true);
+            }
+            for (pos, both_worlds) in switches {
+                tilemap.draw_switch(ctx, assets, pos, both_worlds, true, color);
+            }
+            for (pos, solid) in blocks {
+                tilemap.draw_switch_block(ctx, assets, pos, solid, color);
+            }
+            for c in crumbling.iter().filter(|c| !c.is_broken()) {
+                let shake = if c.ticks / 2 % 2 == 0 { 1. } else { -1. };
+                tilemap.draw_crumbling(ctx, assets, c.tile, Vec2::new(shake, 0.), color);
+            }
+            let ticks = self.entity_ticks as f32 - 1. + alpha;
+            for entity in self.entities.iter().filter(|e| e.world == mode) {
+                let pos = Vec2::lerp(
+                    entity.position(self.entity_ticks.saturating_sub(1)),
+                    entity.position(self.entity_ticks),
+                    alpha,
+                );
+                let spin = ticks * Self::SAW_SPIN;
+                entity.draw(ctx, assets, pos, tilemap.tile_size(), color, spin);
+            }
+        }
+        graphics::reset_blend_state(ctx);
+    }
+
+    /// Draws the player alone, expecting the blend state to be set by the caller.
+    pub fn draw_player(&self, ctx: &mut tetra::Context, assets: &Assets, alpha: f32, color: Color) {
         assets.player.draw(
             ctx,
             DrawParams::new()
-                .position(player_hbox.center())
+                .position(self.interpolated_player_pos(alpha))
                 .origin(Vec2::one() * 8.)
-                .color(Color::WHITE)
+                .color(color)
                 .scale(Vec2::new(
                     if self.player.flip_horizontal() {
                         -1.0
@@ -266,18 +763,730 @@ impl World {
                     },
                 )),
         );
-        let door = if self.got_keys == self.keys_amount {
-            &assets.door
-        } else {
-            &assets.door_locked
-        };
-        door.draw(ctx, DrawParams::new().position(self.end_rect.top_left()));
-        self.dark_tilemap.render_tilemap(ctx, assets, Color::RED);
-        self.light_tilemap.render_tilemap(ctx, assets, Color::BLUE);
-        graphics::reset_blend_state(ctx);
     }
 
     pub fn win(&self) -> bool {
         self.win
     }
+
+    pub fn deaths(&self) -> usize {
+        self.deaths
+    }
+
+    /// Coarse copy of everything that can change while playing, used to tell game states apart.
+    pub fn quantized_state(&self) -> impl Hash + Eq {
+        let mut keys = (self.dark_keys.clone(), self.light_keys.clone());
+        keys.0.sort_unstable();
+        keys.1.sort_unstable();
+        let checkpoint = self.checkpoint.as_ref().map(|c| {
+            let mut keys = (c.dark_keys.clone(), c.light_keys.clone());
+            keys.0.sort_unstable();
+            keys.1.sort_unstable();
+            (c.mode, c.tile, keys)
+        });
+        let mut switched = self.switched.clone();
+        switched.sort_unstable_by_key(|(mode, group)| (*mode as u8, *group));
+        let crumbling = self
+            .crumbling
+            .iter()
+            .map(|c| (c.mode, c.tile, c.ticks))
+            .collect::<Vec<_>>();
+        // Levels without entities shouldn't tell states apart by time
+        let entity_ticks = (!self.entities.is_empty()).then_some(self.entity_ticks);
+        (
+            self.player.quantized_state(),
+            self.mode,
+            keys,
+            checkpoint,
+            crumbling,
+            (switched, self.pressed_switches.clone()),
+            self.teleported_to,
+            self.gravity_zones.clone(),
+            entity_ticks,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tetra::math::Vec2;
+
+    use super::{World, WorldMode};
+    use crate::{
+        input::InputFrame,
+        level::Level,
+        player::Player,
+        tilemap::{Facing, Tile},
+    };
+
+    const DT: f32 = World::TICK;
+
+    fn level() -> Level {
+        Level::from_ascii(
+            "
+            spawn: 1 2
+            door: 8 2
+            dark:
+            ##########
+            #........#
+            #.....^..#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn player_falls_and_lands() {
+        let mut world = World::new(level());
+        let start = world.player_pos();
+        for _ in 0..30 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert_eq!(world.player_pos(), start);
+        assert_eq!(world.deaths(), 0);
+    }
+
+    #[test]
+    fn checkpoint_keeps_keys() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 2
+            door: 8 2
+            dark:
+            ##########
+            #........#
+            #.k.c..^.#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level);
+        let right = InputFrame {
+            right: true,
+            ..Default::default()
+        };
+        for _ in 0..60 {
+            world.step(&right, DT);
+            if world.deaths() > 0 {
+                break;
+            }
+        }
+        assert_eq!(world.deaths(), 1);
+        assert_eq!(world.player_pos(), Vec2::new(4.5, 2.5) * 16.);
+        assert_eq!(world.got_keys, 1);
+
+        world.reset();
+        assert_eq!(world.got_keys, 0);
+        assert!(world.checkpoint.is_none());
+    }
+
+    #[test]
+    fn touching_checkpoint_again_commits_new_keys() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 2
+            door: 8 2
+            dark:
+            ##########
+            #........#
+            #.c.k..^.#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level);
+        let left = InputFrame {
+            left: true,
+            ..Default::default()
+        };
+        let right = InputFrame {
+            right: true,
+            ..Default::default()
+        };
+        while world.got_keys == 0 {
+            world.step(&right, DT);
+        }
+        for _ in 0..30 {
+            world.step(&left, DT);
+        }
+        for _ in 0..60 {
+            world.step(&right, DT);
+            if world.deaths() > 0 {
+                break;
+            }
+        }
+        assert_eq!(world.deaths(), 1);
+        assert_eq!(world.player_pos(), Vec2::new(2.5, 2.5) * 16.);
+        assert_eq!(world.got_keys, 1);
+    }
+
+    #[test]
+    fn respawning_with_colored_key_still_opens_door() {
+        let level = Level::from_ascii(
+            "
+            spawn: 3 2
+            door: 8 2
+            dark:
+            ##########
+            #........#
+            #^..r.c..#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level);
+        let left = InputFrame {
+            left: true,
+            ..Default::default()
+        };
+        let right = InputFrame {
+            right: true,
+            ..Default::default()
+        };
+        while world.checkpoint.is_none() {
+            world.step(&right, DT);
+        }
+        while world.deaths() == 0 {
+            world.step(&left, DT);
+        }
+        assert_eq!(world.got_keys, 0);
+        for _ in 0..60 {
+            world.step(&right, DT);
+        }
+        assert!(world.win());
+    }
+
+    #[test]
+    fn colored_key_opens_locks() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 2
+            door: 8 2
+            dark:
+            ##########
+            #........#
+            #..r.R...#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level.clone());
+        let right = InputFrame {
+            right: true,
+            ..Default::default()
+        };
+        for _ in 0..90 {
+            world.step(&right, DT);
+        }
+        assert!(world.win());
+
+        // Without the key, the lock stops the player
+        let mut level = level;
+        level.dark_tilemap.set_tile_usize((3, 2), Tile::None);
+        let mut world = World::new(level);
+        for _ in 0..90 {
+            world.step(&right, DT);
+        }
+        assert!(!world.win());
+        assert!(world.player_pos().x < 5. * 16.);
+    }
+
+    #[test]
+    fn one_way_platforms() {
+        let level = |spawn_x: usize| {
+            Level::from_ascii(&format!(
+                "
+                spawn: {} 1
+                door: 8 3
+                dark:
+                ##########
+                #........#
+                #=_......#
+                #........#
+                ##########
+                light:
+                ..........
+                ..........
+                ..........
+                ..........
+                ..........
+                ",
+                spawn_x
+            ))
+            .unwrap()
+        };
+        // Landing on a platform facing up
+        let mut world = World::new(level(1));
+        let start = world.player_pos();
+        for _ in 0..30 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert_eq!(world.player_pos(), start);
+
+        // Falling through a platform facing down
+        let mut world = World::new(level(2));
+        for _ in 0..30 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert_eq!(world.player_pos(), Vec2::new(2.5, 3.5) * 16.);
+    }
+
+    #[test]
+    fn one_way_platforms_follow_gravity() {
+        let level = Level::from_ascii(
+            "
+            spawn: 2 3
+            door: 8 3
+            dark:
+            ##########
+            #._......#
+            #........#
+            #.A......#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        // Pulled up by a gravity zone onto a platform facing down
+        let mut world = World::new(level);
+        for _ in 0..30 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert_eq!(world.player.fall_direction(), Player::FALL_UP);
+        assert_eq!(world.player_pos(), Vec2::new(2.5, 2.5) * 16.);
+
+        let level = Level::from_ascii(
+            "
+            spawn: 2 1
+            door: 8 1
+            dark:
+            ##########
+            #.A......#
+            #........#
+            #.=......#
+            #........#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        // Knocked down through a platform facing up while falling up
+        let mut world = World::new(level);
+        world.step(&InputFrame::default(), DT);
+        assert_eq!(world.player.fall_direction(), Player::FALL_UP);
+        world.player.on_spring(Facing::Down, 6.);
+        let mut lowest = world.player_pos().y;
+        for _ in 0..30 {
+            world.step(&InputFrame::default(), DT);
+            lowest = lowest.max(world.player_pos().y);
+        }
+        assert!(lowest > 3.5 * 16.);
+    }
+
+    #[test]
+    fn sideways_one_way_platforms() {
+        let level = |spawn_x: usize| {
+            Level::from_ascii(&format!(
+                "
+                spawn: {} 1
+                door: 8 1
+                dark:
+                ##########
+                #...[....#
+                ##########
+                light:
+                ..........
+                ..........
+                ..........
+                ",
+                spawn_x
+            ))
+            .unwrap()
+        };
+        let left = InputFrame {
+            left: true,
+            ..Default::default()
+        };
+        let right = InputFrame {
+            right: true,
+            ..Default::default()
+        };
+        // Stopped coming from the side it faces
+        let mut world = World::new(level(2));
+        for _ in 0..60 {
+            world.step(&right, DT);
+        }
+        assert_eq!(world.player_pos(), Vec2::new(3.5, 1.5) * 16.);
+
+        // Going through from the other side, in either gravity
+        for fall_direction in [Player::FALL_DOWN, Player::FALL_UP] {
+            let mut world = World::new(level(6));
+            world.player.set_fall_direction(fall_direction);
+            for _ in 0..60 {
+                world.step(&left, DT);
+            }
+            assert_eq!(world.player_pos(), Vec2::new(1.5, 1.5) * 16.);
+        }
+    }
+
+    #[test]
+    fn crumbling_block_breaks_and_comes_back() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 1
+            door: 8 3
+            dark:
+            ##########
+            #........#
+            #%.......#
+            #........#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level);
+        let start = world.player_pos();
+        for _ in 0..20 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert_eq!(world.player_pos(), start);
+        for _ in 0..World::CRUMBLE_TICKS + 20 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert_eq!(world.player_pos(), Vec2::new(1.5, 3.5) * 16.);
+        for _ in 0..World::CRUMBLE_RESPAWN_TICKS {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert!(world.crumbling.is_empty());
+    }
+
+    #[test]
+    fn platform_carries_player_and_saw_kills() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 1
+            door: 8 1
+            platform: dark 1 1 2 5 2
+            saw: dark 1 8 4 1 4
+            dark:
+            ##########
+            #........#
+            #........#
+            #........#
+            #........#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level);
+        for _ in 0..40 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert_eq!(world.player_pos(), Vec2::new(56., 16.) + 8.);
+        assert_eq!(world.deaths(), 0);
+
+        world.reset();
+        world.player.carry(Vec2::new(0., 32.));
+        for _ in 0..120 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert_eq!(world.deaths(), 1);
+    }
+
+    #[test]
+    fn sideways_spring_takes_away_control() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 2
+            door: 8 1
+            spring: dark 1 2 4
+            dark:
+            ##########
+            #........#
+            #}.......#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level);
+        let start = world.player_pos();
+        let left = InputFrame {
+            left: true,
+            ..Default::default()
+        };
+        for _ in 0..5 {
+            world.step(&left, DT);
+        }
+        // Springs push after the player moved, so the first tick only launches them
+        assert_eq!(world.player_pos(), start + Vec2::new(16., 0.));
+    }
+
+    #[test]
+    fn conveyors_carry_in_both_gravities() {
+        let level = Level::from_ascii(
+            "
+            spawn: 4 2
+            door: 8 1
+            dark:
+            ##########
+            #........#
+            #........#
+            #))))))))#
+            light:
+            #((((((((#
+            #........#
+            #........#
+            ##########
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level);
+        let start = world.player_pos();
+        for _ in 0..20 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert!(world.player_pos().x > start.x + 16.);
+        assert_eq!(world.player_pos().y, start.y);
+
+        world.reset();
+        world.mode = WorldMode::Light;
+        world.player.on_world_change(WorldMode::Light);
+        for _ in 0..60 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert!(world.player_pos().x < start.x - 16.);
+        assert_eq!(world.player_pos().y, 24.);
+    }
+
+    #[test]
+    fn ice_is_slow_to_start_and_stop() {
+        let level = |floor: &str| {
+            Level::from_ascii(&format!(
+                "
+                spawn: 1 2
+                door: 18 1
+                dark:
+                ####################
+                #..................#
+                #..................#
+                {floor}
+                light:
+                ####################
+                ####################
+                ####################
+                ####################
+                "
+            ))
+            .unwrap()
+        };
+        let right = InputFrame {
+            right: true,
+            ..Default::default()
+        };
+        let mut ground = World::new(level("####################"));
+        let mut ice = World::new(level("#iiiiiiiiiiiiiiiiii#"));
+        let start = ice.player_pos();
+        for _ in 0..10 {
+            ground.step(&right, DT);
+            ice.step(&right, DT);
+        }
+        assert!(ice.player_pos().x < ground.player_pos().x);
+        assert!(ice.player_pos().x > start.x);
+
+        let (ground_stop, ice_stop) = (ground.player_pos(), ice.player_pos());
+        for _ in 0..10 {
+            ground.step(&InputFrame::default(), DT);
+            ice.step(&InputFrame::default(), DT);
+        }
+        assert!(ground.player_pos().x - ground_stop.x < 16.);
+        assert!(ice.player_pos().x - ice_stop.x > 0.);
+        assert_eq!(ice.player_pos().y, start.y);
+    }
+
+    #[test]
+    fn switch_opens_blocks_until_reset() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 1
+            door: 8 1
+            dark:
+            ##########
+            #..s..w..#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level);
+        let right = InputFrame {
+            right: true,
+            ..Default::default()
+        };
+        for _ in 0..60 {
+            world.step(&right, DT);
+        }
+        assert!(world.win());
+        assert_eq!(world.switched, vec![(WorldMode::Dark, 0)]);
+        world.reset();
+        assert!(world.switched.is_empty());
+    }
+
+    #[test]
+    fn teleporters_keep_momentum() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 1
+            door: 8 3
+            dark:
+            ##########
+            #.t...t..#
+            ##########
+            #........#
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level);
+        let start = world.player_pos();
+        let right = InputFrame {
+            right: true,
+            ..Default::default()
+        };
+        let mut teleported = false;
+        for _ in 0..8 {
+            let before = world.player_pos();
+            world.step(&right, DT);
+            if world.player_pos().x - before.x > 16. {
+                // The player is drawn at the exit right away, not sliding towards it
+                assert_eq!(world.interpolated_player_pos(0.5), world.player_pos());
+                teleported = true;
+            }
+        }
+        assert!(teleported);
+        // Walked 23.25 pixels while speeding up, plus the 4 tiles between the teleporters
+        assert_eq!(world.player_pos(), start + Vec2::new(23.25 + 64., 0.));
+        for _ in 0..30 {
+            world.step(&right, DT);
+        }
+        assert_eq!(world.player_pos().x, 8.5 * 16.);
+    }
+
+    #[test]
+    fn gravity_zone_flips_without_changing_world() {
+        let level = Level::from_ascii(
+            "
+            spawn: 1 3
+            door: 8 3
+            dark:
+            ##########
+            #........#
+            #........#
+            #..~.....#
+            ##########
+            light:
+            ..........
+            ..........
+            ..........
+            ..........
+            ..........
+            ",
+        )
+        .unwrap();
+        let mut world = World::new(level);
+        let right = InputFrame {
+            right: true,
+            ..Default::default()
+        };
+        for _ in 0..8 {
+            world.step(&right, DT);
+        }
+        for _ in 0..60 {
+            world.step(&InputFrame::default(), DT);
+        }
+        assert_eq!(world.mode, WorldMode::Dark);
+        assert_eq!(world.player.fall_direction(), Player::FALL_UP);
+        assert_eq!(world.player_pos().y, 24.);
+        assert_eq!(world.deaths(), 0);
+    }
+
+    #[test]
+    fn walking_into_spike_resets() {
+        let mut world = World::new(level());
+        let start = world.player_pos();
+        let right = InputFrame {
+            right: true,
+            ..Default::default()
+        };
+        for _ in 0..60 {
+            world.step(&right, DT);
+            if world.deaths() > 0 {
+                break;
+            }
+        }
+        assert_eq!(world.deaths(), 1);
+        assert_eq!(world.player_pos(), start);
+        assert!(!world.win());
+    }
 }